@@ -1,6 +1,6 @@
 use std::{
     collections::{BTreeMap, HashMap},
-    sync::mpsc::Receiver,
+    sync::mpsc::{Receiver, Sender},
 };
 
 use itertools::Itertools;
@@ -14,20 +14,26 @@ use quic_geyser_common::{
 };
 use solana_sdk::pubkey::Pubkey;
 
+/// Built blocks are sent to `output` and, if set, to `extra_output`.
+/// With `entry_notifications`, a block is only complete once all its entries were received.
 pub fn start_block_building_thread(
     channel_messages: Receiver<ChannelMessage>,
     output: mio_channel::Sender<ChannelMessage>,
+    extra_output: Option<Sender<ChannelMessage>>,
     compression_type: CompressionType,
     build_blocks_with_accounts: bool,
-) {
+    entry_notifications: bool,
+) -> std::thread::JoinHandle<()> {
     std::thread::spawn(move || {
         build_blocks(
             channel_messages,
             output,
+            extra_output,
             compression_type,
             build_blocks_with_accounts,
+            entry_notifications,
         );
-    });
+    })
 }
 
 #[derive(Default)]
@@ -35,13 +41,29 @@ struct PartialBlock {
     meta: Option<BlockMeta>,
     transactions: Vec<Transaction>,
     account_updates: HashMap<Pubkey, AccountData>,
+    // entries received, stays 0 when entry notifications are disabled
+    entries_count: u64,
+}
+
+impl PartialBlock {
+    /// All the transactions of the block meta were received and, when entries are notified,
+    /// all its entries too.
+    fn is_complete(&self, entry_notifications: bool) -> bool {
+        let Some(meta) = &self.meta else {
+            return false;
+        };
+        meta.executed_transaction_count == self.transactions.len() as u64
+            && (!entry_notifications || meta.entries_count == self.entries_count)
+    }
 }
 
 pub fn build_blocks(
     channel_messages: Receiver<ChannelMessage>,
     output: mio_channel::Sender<ChannelMessage>,
+    extra_output: Option<Sender<ChannelMessage>>,
     compression_type: CompressionType,
     build_blocks_with_accounts: bool,
+    entry_notifications: bool,
 ) {
     let mut partially_build_blocks = BTreeMap::<u64, PartialBlock>::new();
     while let Ok(channel_message) = channel_messages.recv() {
@@ -83,7 +105,9 @@ pub fn build_blocks(
                         &mut partially_build_blocks,
                         slot,
                         &output,
+                        extra_output.as_ref(),
                         compression_type,
+                        entry_notifications,
                     );
                 }
             }
@@ -103,20 +127,21 @@ pub fn build_blocks(
                             partially_build_blocks.get_mut(&meta.slot).unwrap()
                         }
                     };
-                    let meta_tx_count = meta.executed_transaction_count;
                     if partial_block.meta.is_some() {
                         log::error!("Block meta has already been set");
                     } else {
                         partial_block.meta = Some(meta);
                     }
-                    meta_tx_count == partial_block.transactions.len() as u64
+                    partial_block.is_complete(entry_notifications)
                 };
                 if dispatch {
                     dispatch_partial_block(
                         &mut partially_build_blocks,
                         slot,
                         &output,
+                        extra_output.as_ref(),
                         compression_type,
+                        entry_notifications,
                     );
                 }
             }
@@ -128,7 +153,7 @@ pub fn build_blocks(
                     }
                 }
                 // save account updates
-                let (transactions_length_in_pb, meta_transactions_count) = {
+                let dispatch = {
                     let partial_block = match partially_build_blocks.get_mut(&slot) {
                         Some(pb) => pb,
                         None => {
@@ -137,25 +162,47 @@ pub fn build_blocks(
                         }
                     };
                     partial_block.transactions.push(*transaction);
-
-                    let meta_transactions_count = partial_block
-                        .meta
-                        .as_ref()
-                        .map(|x| x.executed_transaction_count);
-
-                    (
-                        partial_block.transactions.len() as u64,
-                        meta_transactions_count,
-                    )
+                    // check if all transactions are taken into account
+                    partial_block.is_complete(entry_notifications)
                 };
 
-                if Some(transactions_length_in_pb) == meta_transactions_count {
-                    // check if all transactions are taken into account
+                if dispatch {
                     dispatch_partial_block(
                         &mut partially_build_blocks,
                         slot,
                         &output,
+                        extra_output.as_ref(),
                         compression_type,
+                        entry_notifications,
+                    );
+                }
+            }
+            ChannelMessage::Entry(entry) => {
+                let slot = entry.slot;
+                if let Some(lowest) = partially_build_blocks.first_entry() {
+                    if *lowest.key() > slot {
+                        log::error!("Entry update is too late the slot data has already been dispactched lowest slot: {}, entry slot: {}", lowest.key(), slot);
+                    }
+                }
+                let dispatch = {
+                    let partial_block = match partially_build_blocks.get_mut(&slot) {
+                        Some(pb) => pb,
+                        None => {
+                            partially_build_blocks.insert(slot, PartialBlock::default());
+                            partially_build_blocks.get_mut(&slot).unwrap()
+                        }
+                    };
+                    partial_block.entries_count += 1;
+                    partial_block.is_complete(entry_notifications)
+                };
+                if dispatch {
+                    dispatch_partial_block(
+                        &mut partially_build_blocks,
+                        slot,
+                        &output,
+                        extra_output.as_ref(),
+                        compression_type,
+                        entry_notifications,
                     );
                 }
             }
@@ -170,7 +217,9 @@ fn dispatch_partial_block(
     partial_blocks: &mut BTreeMap<u64, PartialBlock>,
     slot: u64,
     output: &mio_channel::Sender<ChannelMessage>,
+    extra_output: Option<&Sender<ChannelMessage>>,
     compression_type: CompressionType,
+    entry_notifications: bool,
 ) {
     if let Some(dispatched_partial_block) = partial_blocks.remove(&slot) {
         let Some(meta) = dispatched_partial_block.meta else {
@@ -187,6 +236,13 @@ fn dispatch_partial_block(
                 meta.executed_transaction_count
             );
         }
+        let entries_count = dispatched_partial_block.entries_count;
+        if entry_notifications && entries_count != meta.entries_count {
+            log::error!(
+                "for block at slot {slot} entries count mismatch {entries_count}!={}",
+                meta.entries_count
+            );
+        }
         let accounts = dispatched_partial_block
             .account_updates
             .iter()
@@ -209,6 +265,11 @@ fn dispatch_partial_block(
         match Block::build(meta, transactions, accounts, compression_type) {
             Ok(block) => {
                 log::info!("Dispatching block for slot {}", slot);
+                if let Some(extra_output) = extra_output {
+                    if let Err(e) = extra_output.send(ChannelMessage::Block(block.clone())) {
+                        log::error!("Failed to send block for slot {slot} to extra output: {e}");
+                    }
+                }
                 output.send(ChannelMessage::Block(block)).unwrap();
             }
             Err(e) => {
@@ -11,6 +11,7 @@ use quic_geyser_common::{
     channel_message::{AccountData, ChannelMessage},
     types::{
         block_meta::BlockMeta,
+        entry::Entry,
         slot_identifier::SlotIdentifier,
         transaction::{Transaction, TransactionMeta},
     },
@@ -33,11 +34,14 @@ use crate::block_builder::start_block_building_thread;
 fn test_block_creation_transactions_after_blockmeta() {
     let (channelmsg_sx, cm_rx) = channel();
     let (ms_sx, msg_rx) = mio_channel::channel();
+    let (extra_sx, extra_rx) = channel();
     start_block_building_thread(
         cm_rx,
         ms_sx,
+        Some(extra_sx),
         quic_geyser_common::compression::CompressionType::None,
         true,
+        false,
     );
 
     let acc1_pk = Pubkey::new_unique();
@@ -138,11 +142,13 @@ fn test_block_creation_transactions_after_blockmeta() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -174,11 +180,13 @@ fn test_block_creation_transactions_after_blockmeta() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -210,11 +218,13 @@ fn test_block_creation_transactions_after_blockmeta() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -236,6 +246,10 @@ fn test_block_creation_transactions_after_blockmeta() {
     let ChannelMessage::Block(block) = block_message else {
         unreachable!();
     };
+    assert_eq!(
+        extra_rx.try_recv().unwrap(),
+        ChannelMessage::Block(block.clone())
+    );
     let transactions = block.get_transactions().unwrap();
     let accounts = block.get_accounts().unwrap();
 
@@ -265,8 +279,10 @@ fn test_block_creation_blockmeta_after_transactions() {
     start_block_building_thread(
         cm_rx,
         ms_sx,
+        None,
         quic_geyser_common::compression::CompressionType::None,
         true,
+        false,
     );
 
     let acc1_pk = Pubkey::new_unique();
@@ -364,11 +380,13 @@ fn test_block_creation_blockmeta_after_transactions() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -400,11 +418,13 @@ fn test_block_creation_blockmeta_after_transactions() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -436,11 +456,13 @@ fn test_block_creation_blockmeta_after_transactions() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -495,8 +517,10 @@ fn test_block_creation_incomplete_block_after_slot_notification() {
     start_block_building_thread(
         cm_rx,
         ms_sx,
+        None,
         quic_geyser_common::compression::CompressionType::None,
         true,
+        false,
     );
 
     let acc1_pk = Pubkey::new_unique();
@@ -597,11 +621,13 @@ fn test_block_creation_incomplete_block_after_slot_notification() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -633,11 +659,13 @@ fn test_block_creation_incomplete_block_after_slot_notification() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -669,11 +697,13 @@ fn test_block_creation_incomplete_block_after_slot_notification() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -724,8 +754,10 @@ fn test_block_creation_incomplete_slot() {
     start_block_building_thread(
         cm_rx,
         ms_sx,
+        None,
         quic_geyser_common::compression::CompressionType::None,
         true,
+        false,
     );
 
     let acc1_pk = Pubkey::new_unique();
@@ -827,11 +859,13 @@ fn test_block_creation_incomplete_slot() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -863,11 +897,13 @@ fn test_block_creation_incomplete_slot() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -902,11 +938,13 @@ fn test_block_creation_incomplete_slot() {
             address_table_lookups: vec![],
         },
         is_vote: false,
-        transasction_meta: TransactionMeta {
+        transaction_meta: TransactionMeta {
             error: None,
             fee: 0,
             pre_balances: vec![],
             post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
             inner_instructions: None,
             log_messages: Some(vec!["toto".to_string()]),
             rewards: None,
@@ -955,3 +993,145 @@ fn test_block_creation_incomplete_slot() {
     assert_eq!(transactions, vec![tx1, tx3]);
     assert_eq!(hash_map_accounts, accounts_sent);
 }
+
+#[test]
+fn test_block_creation_waits_for_its_entries() {
+    let (channelmsg_sx, cm_rx) = channel();
+    let (ms_sx, msg_rx) = mio_channel::channel();
+    start_block_building_thread(
+        cm_rx,
+        ms_sx,
+        None,
+        quic_geyser_common::compression::CompressionType::None,
+        false,
+        true,
+    );
+    let entry = |index| {
+        ChannelMessage::Entry(Entry {
+            slot: 5,
+            index,
+            num_hashes: 12500,
+            hash: Hash::new_unique().to_string(),
+            executed_transaction_count: 0,
+            starting_transaction_index: 0,
+        })
+    };
+
+    channelmsg_sx.send(entry(0)).unwrap();
+    let block_meta = BlockMeta {
+        parent_slot: 4,
+        slot: 5,
+        parent_blockhash: Hash::new_unique().to_string(),
+        blockhash: Hash::new_unique().to_string(),
+        rewards: vec![],
+        block_height: Some(4),
+        executed_transaction_count: 0,
+        entries_count: 2,
+        block_time: 0,
+    };
+    channelmsg_sx
+        .send(ChannelMessage::BlockMeta(block_meta.clone()))
+        .unwrap();
+
+    // every transaction is there, one entry is missing
+    sleep(Duration::from_millis(1));
+    assert_eq!(msg_rx.try_recv(), Err(TryRecvError::Empty));
+
+    channelmsg_sx.send(entry(1)).unwrap();
+    sleep(Duration::from_millis(1));
+    let ChannelMessage::Block(block) = msg_rx.try_recv().unwrap() else {
+        unreachable!();
+    };
+    assert_eq!(block.meta, block_meta);
+}
+
+#[test]
+fn test_block_creation_entries_after_blockmeta_and_transactions() {
+    let (channelmsg_sx, cm_rx) = channel();
+    let (ms_sx, msg_rx) = mio_channel::channel();
+    start_block_building_thread(
+        cm_rx,
+        ms_sx,
+        None,
+        quic_geyser_common::compression::CompressionType::None,
+        false,
+        true,
+    );
+
+    let block_meta = BlockMeta {
+        parent_slot: 4,
+        slot: 5,
+        parent_blockhash: Hash::new_unique().to_string(),
+        blockhash: Hash::new_unique().to_string(),
+        rewards: vec![],
+        block_height: Some(4),
+        executed_transaction_count: 1,
+        entries_count: 2,
+        block_time: 0,
+    };
+    channelmsg_sx
+        .send(ChannelMessage::BlockMeta(block_meta.clone()))
+        .unwrap();
+    let tx = Transaction {
+        slot_identifier: SlotIdentifier { slot: 5 },
+        signatures: vec![Signature::new_unique()],
+        message: SolanaMessage {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 0,
+            },
+            account_keys: vec![Pubkey::new_unique()],
+            recent_blockhash: Hash::new_unique(),
+            instructions: vec![],
+            address_table_lookups: vec![],
+        },
+        is_vote: false,
+        transaction_meta: TransactionMeta {
+            error: None,
+            fee: 0,
+            pre_balances: vec![],
+            post_balances: vec![],
+            pre_token_balances: None,
+            post_token_balances: None,
+            inner_instructions: None,
+            log_messages: None,
+            rewards: None,
+            loaded_addresses: LoadedAddresses {
+                writable: vec![],
+                readonly: vec![],
+            },
+            return_data: None,
+            compute_units_consumed: None,
+        },
+        index: 0,
+    };
+    channelmsg_sx
+        .send(ChannelMessage::Transaction(Box::new(tx.clone())))
+        .unwrap();
+
+    // every transaction is there, no entry was received yet
+    sleep(Duration::from_millis(1));
+    assert_eq!(msg_rx.try_recv(), Err(TryRecvError::Empty));
+
+    for index in 0..2 {
+        channelmsg_sx
+            .send(ChannelMessage::Entry(Entry {
+                slot: 5,
+                index,
+                num_hashes: 12500,
+                hash: Hash::new_unique().to_string(),
+                executed_transaction_count: 1 - index,
+                starting_transaction_index: 0,
+            }))
+            .unwrap();
+    }
+    sleep(Duration::from_millis(1));
+    let ChannelMessage::Block(block) = msg_rx.try_recv().unwrap() else {
+        unreachable!();
+    };
+    assert_eq!(block.meta, block_meta);
+    assert_eq!(block.get_transactions().unwrap(), vec![tx]);
+    // the entries completed the block, they did not start another one
+    assert_eq!(msg_rx.try_recv(), Err(TryRecvError::Empty));
+}
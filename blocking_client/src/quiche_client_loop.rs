@@ -464,6 +464,8 @@ mod tests {
                 socket_addr,
                 rx_sent_queue,
                 CompressionType::Lz4Fast(8),
+                mio::Poll::new().unwrap(),
+                Arc::new(AtomicBool::new(false)),
             ) {
                 log::error!("Server loop closed by error : {e}");
             }
@@ -0,0 +1,7 @@
+fn main() -> anyhow::Result<()> {
+    // compiled with protox so building does not need protoc
+    println!("cargo:rerun-if-changed=proto/mq_payload.proto");
+    let file_descriptors = protox::compile(["proto/mq_payload.proto"], ["proto"])?;
+    prost_build::Config::new().compile_fds(file_descriptors)?;
+    Ok(())
+}
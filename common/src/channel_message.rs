@@ -2,7 +2,7 @@ use solana_sdk::{
     account::Account, clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey,
 };
 
-use crate::types::{block::Block, block_meta::BlockMeta, transaction::Transaction};
+use crate::types::{block::Block, block_meta::BlockMeta, entry::Entry, transaction::Transaction};
 use serde::{Serialize, Deserialize};
 
 
@@ -24,4 +24,5 @@ pub enum ChannelMessage {
     BlockMeta(BlockMeta),
     Transaction(Box<Transaction>),
     Block(Block),
+    Entry(Entry),
 }
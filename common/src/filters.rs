@@ -1,9 +1,13 @@
 use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
-use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_sdk::{
+    message::{v0::LoadedAddresses, MessageHeader},
+    pubkey::Pubkey,
+    signature::Signature,
+};
 
-use crate::channel_message::ChannelMessage;
+use crate::{channel_message::ChannelMessage, types::transaction::Transaction};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[repr(C)]
@@ -18,6 +22,8 @@ pub enum Filter {
     BlockAll,
     DeletedAccounts,
     AccountsExcluding(AccountFilter),
+    Transactions(TransactionFilter),
+    Entries,
 }
 
 impl Filter {
@@ -50,6 +56,146 @@ impl Filter {
                 _ => false,
             },
             Filter::AccountsExcluding(account) => !account.allows(message),
+            Filter::Transactions(transaction) => transaction.allows(message),
+            Filter::Entries => matches!(message, ChannelMessage::Entry(_)),
+        }
+    }
+}
+
+/// Transactions selected by the accounts they use, like the transaction filters of the
+/// Yellowstone gRPC plugin.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct TransactionFilter {
+    /// Transactions using at least one of these accounts, any transaction if empty.
+    pub account_include: Vec<Pubkey>,
+    /// Transactions using all of these accounts.
+    pub account_required: Vec<Pubkey>,
+    /// Transactions using none of these accounts, whatever their role.
+    pub account_exclude: Vec<Pubkey>,
+    /// `account_include` only matches accounts signing the transaction.
+    pub signer_only: bool,
+    /// `account_include` only matches accounts the transaction can write.
+    pub writable_only: bool,
+    /// Only vote transactions if true, only non vote ones if false, both if not set.
+    pub vote: Option<bool>,
+    /// Only failed transactions if true, only successful ones if false, both if not set.
+    pub failed: Option<bool>,
+}
+
+impl TransactionFilter {
+    pub fn allows(&self, message: &ChannelMessage) -> bool {
+        match message {
+            ChannelMessage::Transaction(transaction) => self.allows_transaction(
+                &TransactionAccounts::from(transaction.as_ref()),
+                transaction.is_vote,
+                transaction.transaction_meta.error.is_some(),
+            ),
+            _ => false,
+        }
+    }
+
+    /// Matches a transaction from its accounts, so the plugin can filter before converting it.
+    pub fn allows_transaction(
+        &self,
+        accounts: &TransactionAccounts,
+        is_vote: bool,
+        failed: bool,
+    ) -> bool {
+        if self.vote.is_some_and(|vote| vote != is_vote)
+            || self
+                .failed
+                .is_some_and(|filter_failed| filter_failed != failed)
+        {
+            return false;
+        }
+        if accounts
+            .iter()
+            .any(|(account, _, _)| self.account_exclude.contains(account))
+        {
+            return false;
+        }
+
+        // required accounts match whatever their role, e.g. the program of a swap signed by
+        // one of the included wallets
+        let used = accounts
+            .iter()
+            .map(|(account, _, _)| account)
+            .collect::<Vec<_>>();
+        let included = accounts
+            .iter()
+            .filter(|(_, is_signer, is_writable)| {
+                (!self.signer_only || *is_signer) && (!self.writable_only || *is_writable)
+            })
+            .map(|(account, _, _)| account)
+            .collect::<Vec<_>>();
+        (self.account_include.is_empty()
+            || self
+                .account_include
+                .iter()
+                .any(|account| included.contains(&account)))
+            && self
+                .account_required
+                .iter()
+                .all(|account| used.contains(&account))
+    }
+}
+
+/// Accounts of a transaction, the static keys of its message followed by the addresses loaded
+/// from lookup tables.
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionAccounts<'a> {
+    pub header: &'a MessageHeader,
+    pub static_account_keys: &'a [Pubkey],
+    pub loaded_addresses: &'a LoadedAddresses,
+}
+
+impl<'a> TransactionAccounts<'a> {
+    /// Every account with whether it signs the transaction and whether it can be written.
+    ///
+    /// Signers come first in the static keys, then the other accounts, both with their readonly
+    /// accounts last as counted by the header. Loaded addresses never sign.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a Pubkey, bool, bool)> {
+        let header = self.header;
+        let signers = header.num_required_signatures as usize;
+        let writable_signers = signers.saturating_sub(header.num_readonly_signed_accounts as usize);
+        let writable_end = self
+            .static_account_keys
+            .len()
+            .saturating_sub(header.num_readonly_unsigned_accounts as usize);
+        let static_accounts =
+            self.static_account_keys
+                .iter()
+                .enumerate()
+                .map(move |(index, account)| {
+                    if index < signers {
+                        (account, true, index < writable_signers)
+                    } else {
+                        (account, false, index < writable_end)
+                    }
+                });
+        static_accounts
+            .chain(
+                self.loaded_addresses
+                    .writable
+                    .iter()
+                    .map(|account| (account, false, true)),
+            )
+            .chain(
+                self.loaded_addresses
+                    .readonly
+                    .iter()
+                    .map(|account| (account, false, false)),
+            )
+    }
+}
+
+impl<'a> From<&'a Transaction> for TransactionAccounts<'a> {
+    fn from(transaction: &'a Transaction) -> Self {
+        Self {
+            header: &transaction.message.header,
+            static_account_keys: transaction.static_account_keys(),
+            loaded_addresses: &transaction.transaction_meta.loaded_addresses,
         }
     }
 }
@@ -125,11 +271,26 @@ impl AccountFilter {
 
 #[cfg(test)]
 mod tests {
-    use solana_sdk::{account::Account as SolanaAccount, pubkey::Pubkey};
+    use solana_sdk::{
+        account::Account as SolanaAccount,
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+        message::{
+            v0::{LoadedAddresses, Message},
+            MessageHeader,
+        },
+        pubkey::Pubkey,
+        signature::Signature,
+        transaction::TransactionError,
+    };
 
     use crate::{
         channel_message::{AccountData, ChannelMessage},
-        filters::{AccountFilter, AccountFilterType, MemcmpFilter},
+        filters::{AccountFilter, AccountFilterType, MemcmpFilter, TransactionFilter},
+        types::{
+            slot_identifier::SlotIdentifier,
+            transaction::{Transaction, TransactionMeta},
+        },
     };
 
     // asserts are more readable like this
@@ -328,4 +489,146 @@ mod tests {
         assert_eq!(f8.allows(&msg_3), false);
         assert_eq!(f8.allows(&msg_4), true);
     }
+
+    fn transaction(
+        static_account_keys: Vec<Pubkey>,
+        loaded_addresses: LoadedAddresses,
+        is_vote: bool,
+        error: Option<TransactionError>,
+    ) -> ChannelMessage {
+        // forwarded with the loaded addresses after the static keys
+        let account_keys = static_account_keys
+            .into_iter()
+            .chain(loaded_addresses.writable.iter().copied())
+            .chain(loaded_addresses.readonly.iter().copied())
+            .collect();
+        ChannelMessage::Transaction(Box::new(Transaction {
+            slot_identifier: SlotIdentifier { slot: 1 },
+            signatures: vec![Signature::new_unique()],
+            message: Message {
+                // a writable signer, a readonly signer, then one readonly account
+                header: MessageHeader {
+                    num_required_signatures: 2,
+                    num_readonly_signed_accounts: 1,
+                    num_readonly_unsigned_accounts: 1,
+                },
+                account_keys,
+                recent_blockhash: Hash::default(),
+                instructions: vec![],
+                address_table_lookups: vec![],
+            },
+            is_vote,
+            transaction_meta: TransactionMeta {
+                error,
+                fee: 0,
+                pre_balances: vec![],
+                post_balances: vec![],
+                pre_token_balances: None,
+                post_token_balances: None,
+                inner_instructions: None,
+                log_messages: None,
+                rewards: None,
+                loaded_addresses,
+                return_data: None,
+                compute_units_consumed: None,
+            },
+            index: 0,
+        }))
+    }
+
+    #[test]
+    fn test_transaction_filter() {
+        let (payer, cosigner, pool, program) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (loaded_writable, loaded_readonly) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let swap = transaction(
+            vec![payer, cosigner, pool, program],
+            LoadedAddresses {
+                writable: vec![loaded_writable],
+                readonly: vec![loaded_readonly],
+            },
+            false,
+            None,
+        );
+
+        assert!(TransactionFilter::default().allows(&swap));
+        assert!(!TransactionFilter::default().allows(&ChannelMessage::Slot(
+            1,
+            0,
+            CommitmentConfig::processed()
+        )));
+
+        let include = |account_include: Vec<Pubkey>| TransactionFilter {
+            account_include,
+            ..Default::default()
+        };
+        assert!(include(vec![Pubkey::new_unique(), program]).allows(&swap));
+        assert!(include(vec![loaded_readonly]).allows(&swap));
+        assert!(!include(vec![Pubkey::new_unique()]).allows(&swap));
+
+        let required = |account_required: Vec<Pubkey>| TransactionFilter {
+            account_required,
+            ..Default::default()
+        };
+        assert!(required(vec![program, payer, loaded_writable]).allows(&swap));
+        assert!(!required(vec![program, Pubkey::new_unique()]).allows(&swap));
+
+        let exclude = TransactionFilter {
+            account_include: vec![program],
+            account_exclude: vec![loaded_readonly],
+            ..Default::default()
+        };
+        assert!(!exclude.allows(&swap));
+
+        let signed_by = |account: Pubkey| TransactionFilter {
+            account_include: vec![account],
+            signer_only: true,
+            ..Default::default()
+        };
+        assert!(signed_by(payer).allows(&swap));
+        assert!(signed_by(cosigner).allows(&swap));
+        assert!(!signed_by(pool).allows(&swap));
+        assert!(!signed_by(loaded_writable).allows(&swap));
+
+        let writing = |account: Pubkey| TransactionFilter {
+            account_include: vec![account],
+            writable_only: true,
+            ..Default::default()
+        };
+        assert!(writing(payer).allows(&swap));
+        assert!(!writing(cosigner).allows(&swap));
+        assert!(writing(pool).allows(&swap));
+        assert!(!writing(program).allows(&swap));
+        assert!(writing(loaded_writable).allows(&swap));
+        assert!(!writing(loaded_readonly).allows(&swap));
+
+        let vote = transaction(
+            vec![payer, cosigner, program],
+            LoadedAddresses::default(),
+            true,
+            None,
+        );
+        let failed = transaction(
+            vec![payer, cosigner, program],
+            LoadedAddresses::default(),
+            false,
+            Some(TransactionError::AccountNotFound),
+        );
+        let non_vote = TransactionFilter {
+            vote: Some(false),
+            ..Default::default()
+        };
+        assert!(non_vote.allows(&swap));
+        assert!(!non_vote.allows(&vote));
+        let only_failed = TransactionFilter {
+            failed: Some(true),
+            ..Default::default()
+        };
+        assert!(only_failed.allows(&failed));
+        assert!(!only_failed.allows(&swap));
+    }
 }
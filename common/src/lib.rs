@@ -4,7 +4,9 @@ pub mod config;
 pub mod defaults;
 pub mod filters;
 pub mod message;
+pub mod mq_payload;
 pub mod net;
 pub mod plugin_error;
+pub mod protobuf;
 pub mod stream_manager;
 pub mod types;
@@ -6,6 +6,7 @@ use crate::{
         account::Account,
         block::Block,
         block_meta::{BlockMeta, SlotMeta},
+        entry::Entry,
         transaction::Transaction,
     },
 };
@@ -20,6 +21,7 @@ pub enum Message {
     BlockMsg(Block),
     Filters(Vec<Filter>), // sent from client to server
     Ping,
+    EntryMsg(Entry),
 }
 
 impl Message {
@@ -0,0 +1,321 @@
+//! Wire format of the payloads the plugin publishes to the message queue, shared by the plugin
+//! which encodes them and the consumers which decode them.
+//!
+//! The json and bincode encodings are written and read here, protobuf payloads in `protobuf`
+//! from the messages of `common/proto/mq_payload.proto`. `decode` reads all of them.
+
+use std::{borrow::Cow, str::FromStr};
+
+use anyhow::bail;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use solana_sdk::{account::Account, bs58, clock::Slot, pubkey::Pubkey};
+
+use crate::{
+    channel_message::{AccountData, ChannelMessage},
+    compression::CompressionType,
+    protobuf,
+    types::{
+        block::Block,
+        block_meta::{BlockMeta, SlotMeta},
+    },
+};
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const BINCODE_CONTENT_TYPE: &str = "application/x-bincode";
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+/// Debug output of messages which could not be serialized, only sent to the dead-letter exchange.
+pub const TEXT_CONTENT_TYPE: &str = "text/plain";
+/// Content encoding of compressed payloads: an lz4 block prefixed with the uncompressed size as a
+/// little endian u32, what `lz4::block::decompress` reads.
+pub const LZ4_CONTENT_ENCODING: &str = "lz4";
+
+// AMQP headers set on published messages, so consumers can route without parsing the payload
+pub const SLOT_HEADER: &str = "slot";
+pub const COMMITMENT_HEADER: &str = "commitment";
+/// Owner of an updated account.
+pub const OWNER_HEADER: &str = "owner";
+/// Programs invoked by a transaction, including inner instructions.
+pub const PROGRAM_IDS_HEADER: &str = "program_ids";
+// headers of failed transactions
+pub const TRANSACTION_ERROR_HEADER: &str = "transaction_error";
+pub const INSTRUCTION_INDEX_HEADER: &str = "instruction_index";
+pub const INSTRUCTION_ERROR_HEADER: &str = "instruction_error";
+pub const CUSTOM_ERROR_HEADER: &str = "custom_error";
+// headers added to dead-lettered messages
+pub const ERROR_HEADER: &str = "error";
+pub const ORIGINAL_EXCHANGE_HEADER: &str = "original_exchange";
+pub const ORIGINAL_ROUTING_KEY_HEADER: &str = "original_routing_key";
+/// Type of the payload, one of the message types below whatever the encoding.
+pub const MESSAGE_TYPE_HEADER: &str = "message_type";
+
+// message types, named like the messages of `common/proto/mq_payload.proto`
+pub const TRANSACTION_MESSAGE_TYPE: &str = "Transaction";
+pub const ACCOUNT_MESSAGE_TYPE: &str = "AccountUpdate";
+pub const BLOCK_META_MESSAGE_TYPE: &str = "BlockMeta";
+pub const SLOT_MESSAGE_TYPE: &str = "SlotStatus";
+pub const BLOCK_MESSAGE_TYPE: &str = "Block";
+pub const ENTRY_MESSAGE_TYPE: &str = "Entry";
+
+/// Serialization of the MQ payloads, the AMQP content type tells consumers which one is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadEncoding {
+    /// JSON with account data as an array of numbers.
+    #[default]
+    Json,
+    /// JSON with account data as `["<base64 data>", "base64"]`.
+    JsonBase64,
+    /// JSON with account data as `["<base58 data>", "base58"]`.
+    JsonBase58,
+    /// Bincode of the `quic_geyser_common` types.
+    Bincode,
+    /// Protobuf messages described in `common/proto/mq_payload.proto`.
+    Protobuf,
+}
+
+/// Account update as published with the bincode encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    pub account: AccountData,
+    pub slot: Slot,
+    pub is_startup: bool,
+}
+
+/// Json or bincode payload of the messages published as they are: transactions, block metas,
+/// slot status updates and entries.
+pub fn serialize<T: Serialize>(value: &T, encoding: PayloadEncoding) -> anyhow::Result<Vec<u8>> {
+    Ok(match encoding {
+        PayloadEncoding::Json | PayloadEncoding::JsonBase64 | PayloadEncoding::JsonBase58 => {
+            serde_json::to_vec(value)?
+        }
+        PayloadEncoding::Bincode => bincode::serialize(value)?,
+        PayloadEncoding::Protobuf => bail!("protobuf payloads are encoded by `protobuf`"),
+    })
+}
+
+/// Json or bincode payload of an account update.
+pub fn serialize_account(
+    account_data: &AccountData,
+    slot: Slot,
+    is_startup: bool,
+    encoding: PayloadEncoding,
+) -> anyhow::Result<Vec<u8>> {
+    match encoding {
+        PayloadEncoding::Bincode => serialize(
+            &AccountUpdate {
+                account: account_data.clone(),
+                slot,
+                is_startup,
+            },
+            encoding,
+        ),
+        _ => serialize(
+            &account_json(account_data, slot, is_startup, encoding),
+            encoding,
+        ),
+    }
+}
+
+/// Json or bincode payload of a block, its transactions and accounts stay compressed by the
+/// block builder.
+pub fn serialize_block(block: &Block, encoding: PayloadEncoding) -> anyhow::Result<Vec<u8>> {
+    match encoding {
+        PayloadEncoding::Bincode => serialize(block, encoding),
+        _ => serialize(&block_json(block, encoding), encoding),
+    }
+}
+
+/// Payload before its compression, given its AMQP content encoding.
+pub fn decompress<'a>(
+    content_encoding: Option<&str>,
+    data: &'a [u8],
+) -> anyhow::Result<Cow<'a, [u8]>> {
+    match content_encoding {
+        None | Some("") => Ok(Cow::Borrowed(data)),
+        Some(LZ4_CONTENT_ENCODING) => Ok(Cow::Owned(lz4::block::decompress(data, None)?)),
+        Some(content_encoding) => bail!("unsupported content encoding {content_encoding}"),
+    }
+}
+
+/// Decodes a payload published by the plugin back into the message it was built from.
+///
+/// `message_type` is the `MESSAGE_TYPE_HEADER` of the message.
+pub fn decode(
+    message_type: &str,
+    content_type: &str,
+    data: &[u8],
+) -> anyhow::Result<ChannelMessage> {
+    // protobuf content types name their message as a parameter, the header already does
+    let encoding = match content_type.split(';').next().unwrap_or_default().trim() {
+        // the binary data encoding of json payloads is written in the data itself
+        JSON_CONTENT_TYPE => PayloadEncoding::Json,
+        BINCODE_CONTENT_TYPE => PayloadEncoding::Bincode,
+        PROTOBUF_CONTENT_TYPE => return protobuf::decode(message_type, data),
+        _ => bail!("unsupported content type {content_type}"),
+    };
+    let message = match message_type {
+        TRANSACTION_MESSAGE_TYPE => {
+            ChannelMessage::Transaction(Box::new(deserialize(data, encoding)?))
+        }
+        ACCOUNT_MESSAGE_TYPE => {
+            let AccountUpdate {
+                account,
+                slot,
+                is_startup,
+            } = match encoding {
+                PayloadEncoding::Bincode => bincode::deserialize(data)?,
+                _ => serde_json::from_slice::<AccountJson>(data)?.into_update()?,
+            };
+            ChannelMessage::Account(account, slot, is_startup)
+        }
+        BLOCK_META_MESSAGE_TYPE => ChannelMessage::BlockMeta(deserialize(data, encoding)?),
+        SLOT_MESSAGE_TYPE => {
+            let slot_meta: SlotMeta = deserialize(data, encoding)?;
+            ChannelMessage::Slot(
+                slot_meta.slot,
+                slot_meta.parent,
+                slot_meta.commitment_config,
+            )
+        }
+        BLOCK_MESSAGE_TYPE => ChannelMessage::Block(match encoding {
+            PayloadEncoding::Bincode => bincode::deserialize(data)?,
+            _ => serde_json::from_slice::<BlockJson>(data)?.into_block()?,
+        }),
+        ENTRY_MESSAGE_TYPE => ChannelMessage::Entry(deserialize(data, encoding)?),
+        _ => bail!("unknown message type {message_type}"),
+    };
+    Ok(message)
+}
+
+fn deserialize<T: DeserializeOwned>(data: &[u8], encoding: PayloadEncoding) -> anyhow::Result<T> {
+    Ok(match encoding {
+        PayloadEncoding::Bincode => bincode::deserialize(data)?,
+        _ => serde_json::from_slice(data)?,
+    })
+}
+
+/// Binary data of the json encodings. With base64 or base58 it is written like the RPC does,
+/// `["<data>", "<encoding>"]`.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonBytes {
+    Raw(Vec<u8>),
+    Encoded(String, String),
+}
+
+impl JsonBytes {
+    fn new(data: &[u8], encoding: PayloadEncoding) -> Self {
+        match encoding {
+            PayloadEncoding::JsonBase64 => Self::Encoded(BASE64.encode(data), "base64".to_string()),
+            PayloadEncoding::JsonBase58 => {
+                Self::Encoded(bs58::encode(data).into_string(), "base58".to_string())
+            }
+            _ => Self::Raw(data.to_vec()),
+        }
+    }
+
+    fn into_bytes(self) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            Self::Raw(data) => data,
+            Self::Encoded(data, encoding) => match encoding.as_str() {
+                "base64" => BASE64.decode(data)?,
+                "base58" => bs58::decode(data).into_vec()?,
+                _ => bail!("unknown binary encoding {encoding}"),
+            },
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountJson {
+    account: AccountFieldsJson,
+    slot: Slot,
+    is_startup: bool,
+    write_version: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountFieldsJson {
+    pubkey: String,
+    lamports: u64,
+    owner: String,
+    executable: bool,
+    rent_epoch: u64,
+    data: JsonBytes,
+}
+
+impl AccountJson {
+    fn into_update(self) -> anyhow::Result<AccountUpdate> {
+        Ok(AccountUpdate {
+            account: AccountData {
+                pubkey: Pubkey::from_str(&self.account.pubkey)?,
+                account: Account {
+                    lamports: self.account.lamports,
+                    data: self.account.data.into_bytes()?,
+                    owner: Pubkey::from_str(&self.account.owner)?,
+                    executable: self.account.executable,
+                    rent_epoch: self.account.rent_epoch,
+                },
+                write_version: self.write_version,
+            },
+            slot: self.slot,
+            is_startup: self.is_startup,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockJson {
+    meta: BlockMeta,
+    transactions: JsonBytes,
+    accounts_updated_in_block: JsonBytes,
+    accounts_updated_count: u64,
+    compression_type: CompressionType,
+}
+
+impl BlockJson {
+    fn into_block(self) -> anyhow::Result<Block> {
+        Ok(Block {
+            meta: self.meta,
+            transactions: self.transactions.into_bytes()?,
+            accounts_updated_in_block: self.accounts_updated_in_block.into_bytes()?,
+            accounts_updated_count: self.accounts_updated_count,
+            compression_type: self.compression_type,
+        })
+    }
+}
+
+fn account_json(
+    account_data: &AccountData,
+    slot: Slot,
+    is_startup: bool,
+    encoding: PayloadEncoding,
+) -> AccountJson {
+    AccountJson {
+        account: AccountFieldsJson {
+            pubkey: account_data.pubkey.to_string(),
+            lamports: account_data.account.lamports,
+            owner: account_data.account.owner.to_string(),
+            executable: account_data.account.executable,
+            rent_epoch: account_data.account.rent_epoch,
+            data: JsonBytes::new(&account_data.account.data, encoding),
+        },
+        slot,
+        is_startup,
+        write_version: account_data.write_version,
+    }
+}
+
+fn block_json(block: &Block, encoding: PayloadEncoding) -> BlockJson {
+    BlockJson {
+        meta: block.meta.clone(),
+        transactions: JsonBytes::new(&block.transactions, encoding),
+        accounts_updated_in_block: JsonBytes::new(&block.accounts_updated_in_block, encoding),
+        accounts_updated_count: block.accounts_updated_count,
+        compression_type: block.compression_type,
+    }
+}
@@ -0,0 +1,807 @@
+//! Protobuf encoding of the MQ payloads. The types are generated from `proto/mq_payload.proto`,
+//! so the plugin encoding them and the consumers decoding them follow the schema.
+//!
+//! Lists have no presence in proto3: an empty list, e.g. the log messages of a transaction, is
+//! decoded as `None`.
+
+use anyhow::{bail, Context};
+use prost::Message as _;
+use solana_sdk::{
+    account::Account,
+    clock::Slot,
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+    instruction::CompiledInstruction,
+    message::{
+        v0::{LoadedAddresses, Message, MessageAddressTableLookup},
+        MessageHeader,
+    },
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction_context::TransactionReturnData,
+};
+use solana_transaction_status::{Reward, RewardType};
+
+use crate::{
+    channel_message::{AccountData, ChannelMessage},
+    compression::CompressionType,
+    mq_payload::{
+        ACCOUNT_MESSAGE_TYPE, BLOCK_MESSAGE_TYPE, BLOCK_META_MESSAGE_TYPE, ENTRY_MESSAGE_TYPE,
+        SLOT_MESSAGE_TYPE, TRANSACTION_MESSAGE_TYPE,
+    },
+    types::{
+        block::Block,
+        block_meta::{BlockMeta, SlotMeta},
+        entry::Entry,
+        slot_identifier::SlotIdentifier,
+        transaction::{
+            CompiledInstructionSerializable, InnerInstructionSerializable,
+            InnerInstructionsSerializable, Transaction, TransactionMeta,
+            TransactionTokenBalanceSerializable,
+        },
+    },
+};
+
+/// Messages of `proto/mq_payload.proto`.
+pub mod mq {
+    include!(concat!(env!("OUT_DIR"), "/quic_geyser.mq.rs"));
+}
+
+pub const PACKAGE: &str = "quic_geyser.mq";
+
+pub fn encode_account(account_data: &AccountData, slot: Slot, is_startup: bool) -> Vec<u8> {
+    mq::AccountUpdate {
+        pubkey: account_data.pubkey.to_bytes().to_vec(),
+        lamports: account_data.account.lamports,
+        owner: account_data.account.owner.to_bytes().to_vec(),
+        executable: account_data.account.executable,
+        rent_epoch: account_data.account.rent_epoch,
+        data: account_data.account.data.clone(),
+        write_version: account_data.write_version,
+        slot,
+        is_startup,
+    }
+    .encode_to_vec()
+}
+
+pub fn encode_transaction(transaction: &Transaction) -> Vec<u8> {
+    mq::Transaction::from(transaction).encode_to_vec()
+}
+
+pub fn encode_block_meta(block_meta: &BlockMeta) -> Vec<u8> {
+    mq::BlockMeta::from(block_meta).encode_to_vec()
+}
+
+pub fn encode_slot(slot_meta: &SlotMeta) -> Vec<u8> {
+    let commitment = match slot_meta.commitment_config.commitment {
+        CommitmentLevel::Processed => mq::Commitment::Processed,
+        CommitmentLevel::Confirmed => mq::Commitment::Confirmed,
+        CommitmentLevel::Finalized => mq::Commitment::Finalized,
+    };
+    mq::SlotStatus {
+        slot: slot_meta.slot,
+        parent: slot_meta.parent,
+        commitment: commitment.into(),
+    }
+    .encode_to_vec()
+}
+
+pub fn encode_block(block: &Block) -> Vec<u8> {
+    let (compression, compression_level) = match block.compression_type {
+        CompressionType::None => (mq::Compression::None, 0),
+        CompressionType::Lz4Fast(level) => (mq::Compression::Lz4Fast, level),
+        CompressionType::Lz4(level) => (mq::Compression::Lz4, level),
+    };
+    mq::Block {
+        meta: Some((&block.meta).into()),
+        transactions: block.transactions.clone(),
+        accounts_updated_in_block: block.accounts_updated_in_block.clone(),
+        accounts_updated_count: block.accounts_updated_count,
+        compression: compression.into(),
+        compression_level,
+    }
+    .encode_to_vec()
+}
+
+pub fn encode_entry(entry: &Entry) -> Vec<u8> {
+    mq::Entry {
+        slot: entry.slot,
+        index: entry.index,
+        num_hashes: entry.num_hashes,
+        hash: entry.hash.clone(),
+        executed_transaction_count: entry.executed_transaction_count,
+        starting_transaction_index: entry.starting_transaction_index,
+    }
+    .encode_to_vec()
+}
+
+/// Decodes a protobuf payload back into the message it was encoded from, `message_type` is the
+/// name of its message in the schema.
+pub fn decode(message_type: &str, data: &[u8]) -> anyhow::Result<ChannelMessage> {
+    let message = match message_type {
+        TRANSACTION_MESSAGE_TYPE => {
+            ChannelMessage::Transaction(Box::new(mq::Transaction::decode(data)?.try_into()?))
+        }
+        ACCOUNT_MESSAGE_TYPE => {
+            let update = mq::AccountUpdate::decode(data)?;
+            let account_data = AccountData {
+                pubkey: pubkey(&update.pubkey)?,
+                account: Account {
+                    lamports: update.lamports,
+                    data: update.data,
+                    owner: pubkey(&update.owner)?,
+                    executable: update.executable,
+                    rent_epoch: update.rent_epoch,
+                },
+                write_version: update.write_version,
+            };
+            ChannelMessage::Account(account_data, update.slot, update.is_startup)
+        }
+        BLOCK_META_MESSAGE_TYPE => {
+            ChannelMessage::BlockMeta(mq::BlockMeta::decode(data)?.try_into()?)
+        }
+        SLOT_MESSAGE_TYPE => {
+            let status = mq::SlotStatus::decode(data)?;
+            let commitment = match status.commitment() {
+                mq::Commitment::Processed => CommitmentConfig::processed(),
+                mq::Commitment::Confirmed => CommitmentConfig::confirmed(),
+                mq::Commitment::Finalized => CommitmentConfig::finalized(),
+            };
+            ChannelMessage::Slot(status.slot, status.parent, commitment)
+        }
+        BLOCK_MESSAGE_TYPE => {
+            let block = mq::Block::decode(data)?;
+            let compression_type = match block.compression() {
+                mq::Compression::None => CompressionType::None,
+                mq::Compression::Lz4Fast => CompressionType::Lz4Fast(block.compression_level),
+                mq::Compression::Lz4 => CompressionType::Lz4(block.compression_level),
+            };
+            ChannelMessage::Block(Block {
+                meta: block.meta.context("block without meta")?.try_into()?,
+                transactions: block.transactions,
+                accounts_updated_in_block: block.accounts_updated_in_block,
+                accounts_updated_count: block.accounts_updated_count,
+                compression_type,
+            })
+        }
+        ENTRY_MESSAGE_TYPE => {
+            let entry = mq::Entry::decode(data)?;
+            ChannelMessage::Entry(Entry {
+                slot: entry.slot,
+                index: entry.index,
+                num_hashes: entry.num_hashes,
+                hash: entry.hash,
+                executed_transaction_count: entry.executed_transaction_count,
+                starting_transaction_index: entry.starting_transaction_index,
+            })
+        }
+        _ => bail!("unknown message type {message_type}"),
+    };
+    Ok(message)
+}
+
+impl From<&Transaction> for mq::Transaction {
+    fn from(transaction: &Transaction) -> Self {
+        let message = &transaction.message;
+        Self {
+            slot: transaction.slot_identifier.slot,
+            signatures: transaction
+                .signatures
+                .iter()
+                .map(|signature| signature.as_ref().to_vec())
+                .collect(),
+            message: Some(mq::TransactionMessage {
+                header: Some(mq::MessageHeader {
+                    num_required_signatures: message.header.num_required_signatures.into(),
+                    num_readonly_signed_accounts: message
+                        .header
+                        .num_readonly_signed_accounts
+                        .into(),
+                    num_readonly_unsigned_accounts: message
+                        .header
+                        .num_readonly_unsigned_accounts
+                        .into(),
+                }),
+                account_keys: message
+                    .account_keys
+                    .iter()
+                    .map(|account_key| account_key.to_bytes().to_vec())
+                    .collect(),
+                recent_blockhash: message.recent_blockhash.to_bytes().to_vec(),
+                instructions: message
+                    .instructions
+                    .iter()
+                    .map(|instruction| mq::CompiledInstruction {
+                        program_id_index: instruction.program_id_index.into(),
+                        accounts: instruction.accounts.clone(),
+                        data: instruction.data.clone(),
+                    })
+                    .collect(),
+                address_table_lookups: message
+                    .address_table_lookups
+                    .iter()
+                    .map(|lookup| mq::AddressTableLookup {
+                        account_key: lookup.account_key.to_bytes().to_vec(),
+                        writable_indexes: lookup.writable_indexes.clone(),
+                        readonly_indexes: lookup.readonly_indexes.clone(),
+                    })
+                    .collect(),
+            }),
+            is_vote: transaction.is_vote,
+            meta: Some((&transaction.transaction_meta).into()),
+            index: transaction.index,
+        }
+    }
+}
+
+impl TryFrom<mq::Transaction> for Transaction {
+    type Error = anyhow::Error;
+
+    fn try_from(transaction: mq::Transaction) -> anyhow::Result<Self> {
+        let message = transaction.message.context("transaction without message")?;
+        let header = message.header.context("message without header")?;
+        Ok(Self {
+            slot_identifier: SlotIdentifier {
+                slot: transaction.slot,
+            },
+            signatures: transaction
+                .signatures
+                .iter()
+                .map(|signature| Ok(Signature::try_from(signature.as_slice())?))
+                .collect::<anyhow::Result<_>>()?,
+            message: Message {
+                header: MessageHeader {
+                    num_required_signatures: header.num_required_signatures.try_into()?,
+                    num_readonly_signed_accounts: header.num_readonly_signed_accounts.try_into()?,
+                    num_readonly_unsigned_accounts: header
+                        .num_readonly_unsigned_accounts
+                        .try_into()?,
+                },
+                account_keys: pubkeys(&message.account_keys)?,
+                recent_blockhash: Hash::new_from_array(
+                    message.recent_blockhash.as_slice().try_into()?,
+                ),
+                instructions: message
+                    .instructions
+                    .into_iter()
+                    .map(|instruction| {
+                        Ok(CompiledInstruction {
+                            program_id_index: instruction.program_id_index.try_into()?,
+                            accounts: instruction.accounts,
+                            data: instruction.data,
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+                address_table_lookups: message
+                    .address_table_lookups
+                    .into_iter()
+                    .map(|lookup| {
+                        Ok(MessageAddressTableLookup {
+                            account_key: pubkey(&lookup.account_key)?,
+                            writable_indexes: lookup.writable_indexes,
+                            readonly_indexes: lookup.readonly_indexes,
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            },
+            is_vote: transaction.is_vote,
+            transaction_meta: transaction
+                .meta
+                .context("transaction without meta")?
+                .try_into()?,
+            index: transaction.index,
+        })
+    }
+}
+
+impl From<&TransactionMeta> for mq::TransactionMeta {
+    fn from(meta: &TransactionMeta) -> Self {
+        Self {
+            // same representation as the solana confirmed block protobuf
+            error: meta
+                .error
+                .as_ref()
+                .map(|error| bincode::serialize(error).unwrap_or_default()),
+            fee: meta.fee,
+            pre_balances: meta.pre_balances.clone(),
+            post_balances: meta.post_balances.clone(),
+            inner_instructions: meta
+                .inner_instructions
+                .iter()
+                .flatten()
+                .map(|inner| mq::InnerInstructions {
+                    index: inner.index.into(),
+                    instructions: inner
+                        .instructions
+                        .iter()
+                        .map(|inner_instruction| mq::InnerInstruction {
+                            program_id_index: inner_instruction.instruction.program_id_index.into(),
+                            accounts: inner_instruction.instruction.accounts.clone(),
+                            data: inner_instruction.instruction.data.clone(),
+                            stack_height: inner_instruction.stack_height,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            log_messages: meta.log_messages.clone().unwrap_or_default(),
+            pre_token_balances: token_balances(&meta.pre_token_balances),
+            post_token_balances: token_balances(&meta.post_token_balances),
+            rewards: meta.rewards.iter().flatten().map(Into::into).collect(),
+            loaded_writable_addresses: meta
+                .loaded_addresses
+                .writable
+                .iter()
+                .map(|address| address.to_bytes().to_vec())
+                .collect(),
+            loaded_readonly_addresses: meta
+                .loaded_addresses
+                .readonly
+                .iter()
+                .map(|address| address.to_bytes().to_vec())
+                .collect(),
+            return_data: meta.return_data.as_ref().map(|return_data| mq::ReturnData {
+                program_id: return_data.program_id.to_bytes().to_vec(),
+                data: return_data.data.clone(),
+            }),
+            compute_units_consumed: meta.compute_units_consumed,
+        }
+    }
+}
+
+impl TryFrom<mq::TransactionMeta> for TransactionMeta {
+    type Error = anyhow::Error;
+
+    fn try_from(meta: mq::TransactionMeta) -> anyhow::Result<Self> {
+        Ok(Self {
+            error: meta
+                .error
+                .map(|error| bincode::deserialize(&error))
+                .transpose()?,
+            fee: meta.fee,
+            pre_balances: meta.pre_balances,
+            post_balances: meta.post_balances,
+            inner_instructions: non_empty(
+                meta.inner_instructions
+                    .into_iter()
+                    .map(|inner| {
+                        Ok(InnerInstructionsSerializable {
+                            index: inner.index.try_into()?,
+                            instructions: inner
+                                .instructions
+                                .into_iter()
+                                .map(|inner_instruction| {
+                                    Ok(InnerInstructionSerializable {
+                                        stack_height: inner_instruction.stack_height,
+                                        instruction: CompiledInstructionSerializable {
+                                            program_id_index: inner_instruction
+                                                .program_id_index
+                                                .try_into()?,
+                                            accounts: inner_instruction.accounts,
+                                            data: inner_instruction.data,
+                                        },
+                                    })
+                                })
+                                .collect::<anyhow::Result<_>>()?,
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            log_messages: non_empty(meta.log_messages),
+            pre_token_balances: non_empty(
+                meta.pre_token_balances
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            post_token_balances: non_empty(
+                meta.post_token_balances
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            rewards: non_empty(
+                meta.rewards
+                    .into_iter()
+                    .map(TryInto::try_into)
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            loaded_addresses: LoadedAddresses {
+                writable: pubkeys(&meta.loaded_writable_addresses)?,
+                readonly: pubkeys(&meta.loaded_readonly_addresses)?,
+            },
+            return_data: meta
+                .return_data
+                .map(|return_data| {
+                    Ok::<_, anyhow::Error>(TransactionReturnData {
+                        program_id: pubkey(&return_data.program_id)?,
+                        data: return_data.data,
+                    })
+                })
+                .transpose()?,
+            compute_units_consumed: meta.compute_units_consumed,
+        })
+    }
+}
+
+impl From<&TransactionTokenBalanceSerializable> for mq::TokenBalance {
+    fn from(token_balance: &TransactionTokenBalanceSerializable) -> Self {
+        Self {
+            account_index: token_balance.account_index.into(),
+            mint: token_balance.mint.clone(),
+            amount: token_balance.token_amount,
+            owner: token_balance.owner.clone(),
+            program_id: token_balance.program_id.clone(),
+        }
+    }
+}
+
+impl TryFrom<mq::TokenBalance> for TransactionTokenBalanceSerializable {
+    type Error = anyhow::Error;
+
+    fn try_from(token_balance: mq::TokenBalance) -> anyhow::Result<Self> {
+        Ok(Self {
+            account_index: token_balance.account_index.try_into()?,
+            mint: token_balance.mint,
+            token_amount: token_balance.amount,
+            owner: token_balance.owner,
+            program_id: token_balance.program_id,
+        })
+    }
+}
+
+impl From<&BlockMeta> for mq::BlockMeta {
+    fn from(block_meta: &BlockMeta) -> Self {
+        Self {
+            parent_slot: block_meta.parent_slot,
+            slot: block_meta.slot,
+            parent_blockhash: block_meta.parent_blockhash.clone(),
+            blockhash: block_meta.blockhash.clone(),
+            rewards: block_meta.rewards.iter().map(Into::into).collect(),
+            block_height: block_meta.block_height,
+            executed_transaction_count: block_meta.executed_transaction_count,
+            entries_count: block_meta.entries_count,
+            block_time: block_meta.block_time,
+        }
+    }
+}
+
+impl TryFrom<mq::BlockMeta> for BlockMeta {
+    type Error = anyhow::Error;
+
+    fn try_from(block_meta: mq::BlockMeta) -> anyhow::Result<Self> {
+        Ok(Self {
+            parent_slot: block_meta.parent_slot,
+            slot: block_meta.slot,
+            parent_blockhash: block_meta.parent_blockhash,
+            blockhash: block_meta.blockhash,
+            rewards: block_meta
+                .rewards
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<_>>()?,
+            block_height: block_meta.block_height,
+            executed_transaction_count: block_meta.executed_transaction_count,
+            entries_count: block_meta.entries_count,
+            block_time: block_meta.block_time,
+        })
+    }
+}
+
+impl From<&Reward> for mq::Reward {
+    fn from(reward: &Reward) -> Self {
+        let reward_type = match reward.reward_type {
+            None => mq::RewardType::Unspecified,
+            Some(RewardType::Fee) => mq::RewardType::Fee,
+            Some(RewardType::Rent) => mq::RewardType::Rent,
+            Some(RewardType::Staking) => mq::RewardType::Staking,
+            Some(RewardType::Voting) => mq::RewardType::Voting,
+        };
+        Self {
+            pubkey: reward.pubkey.clone(),
+            lamports: reward.lamports,
+            post_balance: reward.post_balance,
+            reward_type: reward_type.into(),
+            commission: reward.commission.map(u32::from),
+        }
+    }
+}
+
+impl TryFrom<mq::Reward> for Reward {
+    type Error = anyhow::Error;
+
+    fn try_from(reward: mq::Reward) -> anyhow::Result<Self> {
+        let reward_type = match reward.reward_type() {
+            mq::RewardType::Unspecified => None,
+            mq::RewardType::Fee => Some(RewardType::Fee),
+            mq::RewardType::Rent => Some(RewardType::Rent),
+            mq::RewardType::Staking => Some(RewardType::Staking),
+            mq::RewardType::Voting => Some(RewardType::Voting),
+        };
+        Ok(Self {
+            pubkey: reward.pubkey,
+            lamports: reward.lamports,
+            post_balance: reward.post_balance,
+            reward_type,
+            commission: reward.commission.map(u8::try_from).transpose()?,
+        })
+    }
+}
+
+fn token_balances(
+    token_balances: &Option<Vec<TransactionTokenBalanceSerializable>>,
+) -> Vec<mq::TokenBalance> {
+    token_balances.iter().flatten().map(Into::into).collect()
+}
+
+fn non_empty<T>(values: Vec<T>) -> Option<Vec<T>> {
+    (!values.is_empty()).then_some(values)
+}
+
+fn pubkey(bytes: &[u8]) -> anyhow::Result<Pubkey> {
+    Ok(Pubkey::try_from(bytes)?)
+}
+
+fn pubkeys(keys: &[Vec<u8>]) -> anyhow::Result<Vec<Pubkey>> {
+    keys.iter().map(|key| pubkey(key)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message as _;
+    use solana_sdk::{
+        account::Account,
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+        instruction::{CompiledInstruction, InstructionError},
+        message::{
+            v0::{LoadedAddresses, Message, MessageAddressTableLookup},
+            MessageHeader,
+        },
+        pubkey::Pubkey,
+        signature::Signature,
+        transaction::TransactionError,
+        transaction_context::TransactionReturnData,
+    };
+    use solana_transaction_status::{Reward, RewardType};
+
+    use super::{
+        decode, encode_account, encode_block, encode_block_meta, encode_entry, encode_slot,
+        encode_transaction, mq,
+    };
+    use crate::{
+        channel_message::{AccountData, ChannelMessage},
+        compression::CompressionType,
+        mq_payload::{
+            ACCOUNT_MESSAGE_TYPE, BLOCK_MESSAGE_TYPE, BLOCK_META_MESSAGE_TYPE, ENTRY_MESSAGE_TYPE,
+            SLOT_MESSAGE_TYPE, TRANSACTION_MESSAGE_TYPE,
+        },
+        types::{
+            block::Block,
+            block_meta::{BlockMeta, SlotMeta},
+            entry::Entry,
+            slot_identifier::SlotIdentifier,
+            transaction::{
+                CompiledInstructionSerializable, InnerInstructionSerializable,
+                InnerInstructionsSerializable, Transaction, TransactionMeta,
+                TransactionTokenBalanceSerializable,
+            },
+        },
+    };
+
+    fn block_meta() -> BlockMeta {
+        BlockMeta {
+            parent_slot: 9,
+            slot: 10,
+            parent_blockhash: Hash::new_unique().to_string(),
+            blockhash: Hash::new_unique().to_string(),
+            rewards: vec![Reward {
+                pubkey: Pubkey::new_unique().to_string(),
+                lamports: -5,
+                post_balance: 100,
+                reward_type: Some(RewardType::Voting),
+                commission: Some(10),
+            }],
+            block_height: Some(8),
+            executed_transaction_count: 3,
+            entries_count: 2,
+            block_time: 1_700_000_000,
+        }
+    }
+
+    // every field set, lists included, so nothing is lost to proto3 defaults
+    fn transaction() -> Transaction {
+        let token_balance = |token_amount| TransactionTokenBalanceSerializable {
+            account_index: 2,
+            mint: Pubkey::new_unique().to_string(),
+            token_amount,
+            owner: Pubkey::new_unique().to_string(),
+            program_id: Pubkey::new_unique().to_string(),
+        };
+        Transaction {
+            slot_identifier: SlotIdentifier { slot: 10 },
+            signatures: vec![Signature::new_unique(), Signature::new_unique()],
+            message: Message {
+                header: MessageHeader {
+                    num_required_signatures: 2,
+                    num_readonly_signed_accounts: 1,
+                    num_readonly_unsigned_accounts: 1,
+                },
+                account_keys: vec![
+                    Pubkey::new_unique(),
+                    Pubkey::new_unique(),
+                    Pubkey::new_unique(),
+                ],
+                recent_blockhash: Hash::new_unique(),
+                instructions: vec![CompiledInstruction {
+                    program_id_index: 2,
+                    accounts: vec![0, 1, 3],
+                    data: vec![1, 2, 3],
+                }],
+                address_table_lookups: vec![MessageAddressTableLookup {
+                    account_key: Pubkey::new_unique(),
+                    writable_indexes: vec![0],
+                    readonly_indexes: vec![1, 2],
+                }],
+            },
+            is_vote: true,
+            transaction_meta: TransactionMeta {
+                error: Some(TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(6001),
+                )),
+                fee: 5000,
+                pre_balances: vec![1_000_000, 0, 1],
+                post_balances: vec![995_000, 0, 1],
+                pre_token_balances: Some(vec![token_balance(10)]),
+                post_token_balances: Some(vec![token_balance(0)]),
+                inner_instructions: Some(vec![InnerInstructionsSerializable {
+                    index: 0,
+                    instructions: vec![InnerInstructionSerializable {
+                        stack_height: Some(2),
+                        instruction: CompiledInstructionSerializable {
+                            program_id_index: 4,
+                            accounts: vec![1],
+                            data: vec![9],
+                        },
+                    }],
+                }]),
+                log_messages: Some(vec!["Program log: failed".to_string()]),
+                rewards: Some(block_meta().rewards),
+                loaded_addresses: LoadedAddresses {
+                    writable: vec![Pubkey::new_unique()],
+                    readonly: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+                },
+                return_data: Some(TransactionReturnData {
+                    program_id: Pubkey::new_unique(),
+                    data: vec![7, 7],
+                }),
+                compute_units_consumed: Some(1400),
+            },
+            index: 42,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let account_data = AccountData {
+            pubkey: Pubkey::new_unique(),
+            account: Account {
+                lamports: 42,
+                data: vec![1, 2, 3, 4],
+                owner: Pubkey::new_unique(),
+                executable: true,
+                rent_epoch: u64::MAX,
+            },
+            write_version: 7,
+        };
+        let transaction = transaction();
+        let slot_meta = SlotMeta {
+            slot: 10,
+            parent: 9,
+            commitment_config: CommitmentConfig::confirmed(),
+        };
+        let entry = Entry {
+            slot: 10,
+            index: 1,
+            num_hashes: 12_500,
+            hash: Hash::new_unique().to_string(),
+            executed_transaction_count: 2,
+            starting_transaction_index: 1,
+        };
+        let block_meta = block_meta();
+        let block = Block::build(
+            block_meta.clone(),
+            vec![transaction.clone()],
+            vec![],
+            CompressionType::Lz4(-3),
+        )
+        .unwrap();
+
+        let cases = [
+            (
+                ACCOUNT_MESSAGE_TYPE,
+                encode_account(&account_data, 10, true),
+                ChannelMessage::Account(account_data, 10, true),
+            ),
+            (
+                TRANSACTION_MESSAGE_TYPE,
+                encode_transaction(&transaction),
+                ChannelMessage::Transaction(Box::new(transaction)),
+            ),
+            (
+                BLOCK_META_MESSAGE_TYPE,
+                encode_block_meta(&block_meta),
+                ChannelMessage::BlockMeta(block_meta),
+            ),
+            (
+                SLOT_MESSAGE_TYPE,
+                encode_slot(&slot_meta),
+                ChannelMessage::Slot(10, 9, CommitmentConfig::confirmed()),
+            ),
+            (
+                ENTRY_MESSAGE_TYPE,
+                encode_entry(&entry),
+                ChannelMessage::Entry(entry),
+            ),
+            (
+                BLOCK_MESSAGE_TYPE,
+                encode_block(&block),
+                ChannelMessage::Block(block),
+            ),
+        ];
+        for (message_type, data, message) in cases {
+            assert_eq!(
+                decode(message_type, &data).unwrap(),
+                message,
+                "{message_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_field_numbers() {
+        // the first fields of an account update, as numbered in the schema
+        let account_data = AccountData {
+            pubkey: Pubkey::new_from_array([1; 32]),
+            account: Account {
+                lamports: 150,
+                ..Account::default()
+            },
+            write_version: 0,
+        };
+        let data = encode_account(&account_data, 0, false);
+        let mut expected = vec![0x0a, 32];
+        expected.extend_from_slice(&[1; 32]);
+        expected.extend_from_slice(&[0x10, 0x96, 0x01]);
+        // owner is the default pubkey, 32 zero bytes
+        expected.extend_from_slice(&[0x1a, 32]);
+        expected.extend_from_slice(&[0; 32]);
+        assert_eq!(data, expected);
+        assert_eq!(
+            mq::AccountUpdate::decode(data.as_slice()).unwrap().lamports,
+            150
+        );
+    }
+
+    #[test]
+    fn test_empty_lists_are_decoded_as_none() {
+        let mut transaction = transaction();
+        transaction.transaction_meta.log_messages = Some(vec![]);
+        let decoded = decode(TRANSACTION_MESSAGE_TYPE, &encode_transaction(&transaction)).unwrap();
+        let ChannelMessage::Transaction(decoded) = decoded else {
+            panic!("not a transaction");
+        };
+        assert_eq!(decoded.transaction_meta.log_messages, None);
+    }
+
+    #[test]
+    fn test_invalid_payloads_are_errors() {
+        assert!(decode(ACCOUNT_MESSAGE_TYPE, &[0x0a, 32]).is_err());
+        let short_pubkey = mq::AccountUpdate {
+            pubkey: vec![1, 2, 3],
+            ..Default::default()
+        };
+        assert!(decode(ACCOUNT_MESSAGE_TYPE, &short_pubkey.encode_to_vec()).is_err());
+        assert!(decode("Unknown", &[]).is_err());
+    }
+}
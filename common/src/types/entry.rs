@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+/// A PoH entry of a slot, as notified by the validator once it is processed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[repr(C)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub slot: u64,
+    /// Position of the entry in its slot.
+    pub index: u64,
+    pub num_hashes: u64,
+    /// Base58 hash of the entry.
+    pub hash: String,
+    pub executed_transaction_count: u64,
+    /// Index in the slot of the first transaction of the entry.
+    pub starting_transaction_index: u64,
+}
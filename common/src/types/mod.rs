@@ -2,5 +2,6 @@ pub mod account;
 pub mod block;
 pub mod block_meta;
 pub mod connections_parameters;
+pub mod entry;
 pub mod slot_identifier;
 pub mod transaction;
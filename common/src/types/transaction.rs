@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use solana_sdk::{
     message::v0::{LoadedAddresses, Message},
+    pubkey::Pubkey,
     signature::Signature,
     transaction::TransactionError,
     transaction_context::TransactionReturnData,
@@ -102,3 +103,137 @@ pub struct Transaction {
     pub index: u64,
 }
 
+impl Transaction {
+    /// Every account of the transaction: the static keys of the message followed by the writable
+    /// and readonly addresses loaded from lookup tables, the order instructions and token
+    /// balances index them in. The plugin forwards them all in `message.account_keys`.
+    pub fn account_keys(&self) -> &[Pubkey] {
+        &self.message.account_keys
+    }
+
+    /// Keys of the message itself, without the addresses loaded from lookup tables.
+    pub fn static_account_keys(&self) -> &[Pubkey] {
+        let account_keys = &self.message.account_keys;
+        let loaded = self.transaction_meta.loaded_addresses.len();
+        &account_keys[..account_keys.len().saturating_sub(loaded)]
+    }
+
+    /// Programs invoked by the transaction, including through cpi, in order of first invocation.
+    pub fn program_ids(&self) -> Vec<Pubkey> {
+        let account_keys = self.account_keys();
+
+        let inner_instructions = self
+            .transaction_meta
+            .inner_instructions
+            .iter()
+            .flatten()
+            .flat_map(|inner| inner.instructions.iter())
+            .map(|inner| inner.instruction.program_id_index);
+        let program_id_indexes = self
+            .message
+            .instructions
+            .iter()
+            .map(|instruction| instruction.program_id_index)
+            .chain(inner_instructions);
+
+        let mut program_ids = vec![];
+        for index in program_id_indexes {
+            if let Some(program_id) = account_keys.get(index as usize) {
+                if !program_ids.contains(program_id) {
+                    program_ids.push(*program_id);
+                }
+            }
+        }
+        program_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        hash::Hash,
+        instruction::CompiledInstruction,
+        message::{
+            v0::{LoadedAddresses, Message},
+            MessageHeader,
+        },
+        pubkey::Pubkey,
+    };
+
+    use super::{
+        CompiledInstructionSerializable, InnerInstructionSerializable,
+        InnerInstructionsSerializable, Transaction, TransactionMeta,
+    };
+    use crate::types::slot_identifier::SlotIdentifier;
+
+    #[test]
+    fn test_program_ids() {
+        let payer = Pubkey::new_unique();
+        let program_1 = Pubkey::new_unique();
+        let program_2 = Pubkey::new_unique();
+        let loaded_program = Pubkey::new_unique();
+        let instruction = |program_id_index| CompiledInstruction {
+            program_id_index,
+            accounts: vec![0],
+            data: vec![],
+        };
+
+        let transaction = Transaction {
+            slot_identifier: SlotIdentifier { slot: 1 },
+            signatures: vec![],
+            message: Message {
+                header: MessageHeader {
+                    num_required_signatures: 1,
+                    num_readonly_signed_accounts: 0,
+                    num_readonly_unsigned_accounts: 2,
+                },
+                account_keys: vec![payer, program_1, program_2, loaded_program],
+                recent_blockhash: Hash::default(),
+                instructions: vec![instruction(2), instruction(1), instruction(2)],
+                address_table_lookups: vec![],
+            },
+            is_vote: false,
+            transaction_meta: TransactionMeta {
+                error: None,
+                fee: 0,
+                pre_balances: vec![],
+                post_balances: vec![],
+                pre_token_balances: None,
+                post_token_balances: None,
+                inner_instructions: Some(vec![InnerInstructionsSerializable {
+                    index: 0,
+                    instructions: vec![InnerInstructionSerializable {
+                        stack_height: Some(2),
+                        instruction: CompiledInstructionSerializable {
+                            program_id_index: 3,
+                            accounts: vec![],
+                            data: vec![],
+                        },
+                    }],
+                }]),
+                log_messages: None,
+                rewards: None,
+                loaded_addresses: LoadedAddresses {
+                    writable: vec![],
+                    readonly: vec![loaded_program],
+                },
+                return_data: None,
+                compute_units_consumed: None,
+            },
+            index: 0,
+        };
+
+        assert_eq!(
+            transaction.program_ids(),
+            vec![program_2, program_1, loaded_program]
+        );
+        assert_eq!(
+            transaction.account_keys(),
+            vec![payer, program_1, program_2, loaded_program]
+        );
+        assert_eq!(
+            transaction.static_account_keys(),
+            vec![payer, program_1, program_2]
+        );
+    }
+}
@@ -216,6 +216,9 @@ fn blocking(args: Args, client_stats: ClientStats, break_thread: Arc<AtomicBool>
                 quic_geyser_common::message::Message::Ping => {
                     // not supported
                 }
+                quic_geyser_common::message::Message::EntryMsg(entry) => {
+                    log::trace!("got entry notification : {} {}", entry.slot, entry.index);
+                }
             }
         }
         log::info!("breaking client thread");
@@ -337,6 +340,9 @@ async fn non_blocking(args: Args, client_stats: ClientStats, break_thread: Arc<A
                 quic_geyser_common::message::Message::Ping => {
                     // not supported
                 }
+                quic_geyser_common::message::Message::EntryMsg(entry) => {
+                    log::trace!("got entry notification : {} {}", entry.slot, entry.index);
+                }
             }
         }
 
@@ -0,0 +1,234 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use futures::{stream::BoxStream, StreamExt};
+use lapin::{
+    acker::Acker,
+    options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions},
+    types::{AMQPValue, FieldTable},
+    Channel, Connection, ConnectionProperties,
+};
+use quic_geyser_common::{
+    channel_message::ChannelMessage,
+    mq_payload::{decode, decompress, MESSAGE_TYPE_HEADER},
+};
+use solana_sdk::clock::Slot;
+
+use crate::mq_stream::{slot_offset, AmqpStreamProbe, StreamOffset};
+
+const REPLY_SUCCESS: u16 = 200;
+// how long a stream probe waits for a message before concluding there is none
+const STREAM_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Decoded messages of a queue, see `MqConsumer::consume`.
+pub type DeliveryStream = BoxStream<'static, anyhow::Result<Delivery>>;
+
+/// Consumes the queues the plugin publishes to, with the payload decoding of the plugin so
+/// consumers get back the `quic_geyser_common` types whatever the configured encoding.
+pub struct MqConsumer {
+    connection: Connection,
+    channel: Channel,
+    // numbers the consumer tags, which have to be unique on the channel
+    consumers: AtomicU64,
+}
+
+impl MqConsumer {
+    /// At most `prefetch` messages are delivered before being acked or rejected, 0 is unlimited.
+    /// Stream queues need a prefetch.
+    pub async fn connect(amqp_url: &str, prefetch: u16) -> anyhow::Result<Self> {
+        let connection = Connection::connect(amqp_url, ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        channel
+            .basic_qos(prefetch, BasicQosOptions::default())
+            .await?;
+        Ok(Self {
+            connection,
+            channel,
+            consumers: AtomicU64::new(0),
+        })
+    }
+
+    /// Stream of the messages of `queue`, each of them has to be acked or rejected.
+    /// A queue can be consumed several times, every stream gets its own consumer tag.
+    ///
+    /// Messages which cannot be decoded are left unacked, the stream yields an
+    /// `UndecodableDelivery` error the consumer acks, rejects or requeues them with.
+    pub async fn consume(&self, queue: &str) -> anyhow::Result<DeliveryStream> {
+        self.consume_with_arguments(queue, FieldTable::default())
+            .await
+    }
+
+    /// Like `consume` for a stream queue, starting at `offset`.
+    pub async fn consume_stream(
+        &self,
+        queue: &str,
+        offset: StreamOffset,
+    ) -> anyhow::Result<DeliveryStream> {
+        self.consume_with_arguments(queue, offset.consume_arguments())
+            .await
+    }
+
+    /// Like `consume` for a stream queue, starting at the messages of `slot`.
+    /// Messages of earlier slots can still follow, see `mq_stream::slot_offset`.
+    pub async fn consume_stream_from_slot(
+        &self,
+        queue: &str,
+        slot: Slot,
+    ) -> anyhow::Result<DeliveryStream> {
+        let mut probe = AmqpStreamProbe::new(&self.connection, queue, STREAM_PROBE_TIMEOUT).await?;
+        let offset = slot_offset(&mut probe, slot).await?;
+        probe.close().await?;
+        log::info!("Consuming stream {queue} from slot {slot} at {offset:?}");
+        self.consume_stream(queue, offset).await
+    }
+
+    async fn consume_with_arguments(
+        &self,
+        queue: &str,
+        arguments: FieldTable,
+    ) -> anyhow::Result<DeliveryStream> {
+        let consumer_number = self.consumers.fetch_add(1, Ordering::Relaxed) + 1;
+        let consumer = self
+            .channel
+            .basic_consume(
+                queue,
+                &format!("quic-geyser-mq-consumer-{queue}-{consumer_number}"),
+                BasicConsumeOptions::default(),
+                arguments,
+            )
+            .await?;
+        Ok(consumer
+            .then(|delivery| async move { Delivery::decode(delivery?).await })
+            .boxed())
+    }
+
+    pub async fn close(self) -> anyhow::Result<()> {
+        self.channel.close(REPLY_SUCCESS, "consumer closed").await?;
+        self.connection
+            .close(REPLY_SUCCESS, "consumer closed")
+            .await?;
+        Ok(())
+    }
+}
+
+/// A decoded message, the broker delivers it again if it is not acked before the connection closes.
+#[derive(Debug)]
+pub struct Delivery {
+    pub message: ChannelMessage,
+    /// Deterministic id set by the plugin, messages published again after a broker failure or
+    /// a plugin restart can be deduplicated on it.
+    pub message_id: Option<String>,
+    /// The message was delivered before without being acked.
+    pub redelivered: bool,
+    acker: Acker,
+}
+
+impl Delivery {
+    /// Decodes a delivery of the queues the plugin publishes to, for consumers driving their own
+    /// lapin consumer. A message which cannot be decoded is left unacked and returned in an
+    /// `UndecodableDelivery` error.
+    pub async fn decode(delivery: lapin::message::Delivery) -> anyhow::Result<Self> {
+        let properties = &delivery.properties;
+        let content_type = properties
+            .content_type()
+            .as_ref()
+            .map(|content_type| content_type.as_str())
+            .unwrap_or_default();
+        let message_type = properties
+            .headers()
+            .as_ref()
+            .and_then(|headers| {
+                headers
+                    .inner()
+                    .iter()
+                    .find(|(name, _)| name.as_str() == MESSAGE_TYPE_HEADER)
+            })
+            .and_then(|(_, value)| match value {
+                AMQPValue::LongString(value) => {
+                    Some(String::from_utf8_lossy(value.as_bytes()).into_owned())
+                }
+                _ => None,
+            })
+            .unwrap_or_default();
+        let content_encoding = properties
+            .content_encoding()
+            .as_ref()
+            .map(|content_encoding| content_encoding.as_str());
+
+        let decoded = decompress(content_encoding, &delivery.data)
+            .and_then(|data| decode(&message_type, content_type, &data));
+        match decoded {
+            Ok(message) => Ok(Self {
+                message,
+                message_id: properties
+                    .message_id()
+                    .as_ref()
+                    .map(|message_id| message_id.to_string()),
+                redelivered: delivery.redelivered,
+                acker: delivery.acker,
+            }),
+            Err(error) => Err(UndecodableDelivery {
+                message_type,
+                content_type: content_type.to_string(),
+                delivery,
+                error,
+            }
+            .into()),
+        }
+    }
+
+    pub async fn ack(&self) -> anyhow::Result<()> {
+        self.acker.ack(BasicAckOptions::default()).await?;
+        Ok(())
+    }
+
+    /// With `requeue` the message is delivered again, otherwise it is dead-lettered or dropped.
+    pub async fn reject(&self, requeue: bool) -> anyhow::Result<()> {
+        self.acker
+            .nack(BasicNackOptions {
+                requeue,
+                ..Default::default()
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// A message which could not be decoded, e.g. published by a newer plugin. It is not acked so it
+/// is not lost, the consumer decides what to do with it through `delivery`.
+pub struct UndecodableDelivery {
+    pub message_type: String,
+    pub content_type: String,
+    pub delivery: lapin::message::Delivery,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Debug for UndecodableDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UndecodableDelivery")
+            .field("message_type", &self.message_type)
+            .field("content_type", &self.content_type)
+            .field("delivery_tag", &self.delivery.delivery_tag)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl fmt::Display for UndecodableDelivery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "undecodable {} message with content type {}",
+            self.message_type, self.content_type
+        )
+    }
+}
+
+impl std::error::Error for UndecodableDelivery {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
@@ -0,0 +1,192 @@
+//! Replay of stream queues (`queue_type` `stream` in the plugin config) from an offset, a time or
+//! a slot.
+
+use std::{future::Future, time::Duration};
+
+use anyhow::Context;
+use futures::StreamExt;
+use lapin::{
+    options::{BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicQosOptions},
+    types::{AMQPValue, FieldTable},
+    Channel, Connection,
+};
+use quic_geyser_common::mq_payload::SLOT_HEADER;
+use solana_sdk::clock::Slot;
+
+/// Consume argument telling where a stream consumer starts, the broker sets it on every delivery.
+pub const STREAM_OFFSET_ARGUMENT: &str = "x-stream-offset";
+
+const REPLY_SUCCESS: u16 = 200;
+
+/// Where a consumer of a stream queue starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamOffset {
+    /// The oldest message kept by the stream.
+    First,
+    /// The last chunk of messages written to the stream.
+    Last,
+    /// Messages published once the consumer started.
+    Next,
+    Offset(u64),
+    /// Messages published from this unix time in seconds, the broker starts at the chunk holding it.
+    Timestamp(u64),
+}
+
+impl StreamOffset {
+    pub fn argument(&self) -> AMQPValue {
+        match *self {
+            StreamOffset::First => AMQPValue::LongString("first".into()),
+            StreamOffset::Last => AMQPValue::LongString("last".into()),
+            StreamOffset::Next => AMQPValue::LongString("next".into()),
+            StreamOffset::Offset(offset) => AMQPValue::LongLongInt(offset as i64),
+            StreamOffset::Timestamp(timestamp) => AMQPValue::Timestamp(timestamp),
+        }
+    }
+
+    /// Arguments of a `basic_consume` starting at this offset.
+    pub fn consume_arguments(&self) -> FieldTable {
+        let mut arguments = FieldTable::default();
+        arguments.insert(STREAM_OFFSET_ARGUMENT.into(), self.argument());
+        arguments
+    }
+}
+
+/// Looks at single messages of a stream.
+pub trait StreamProbe {
+    /// Offset and slot of the first message at `offset` or after it, `None` if there is none.
+    fn probe(
+        &mut self,
+        offset: StreamOffset,
+    ) -> impl Future<Output = anyhow::Result<Option<(u64, Slot)>>>;
+}
+
+/// Offset to consume a stream from to get the messages of `slot` and of later slots.
+///
+/// Messages are published about in slot order, so the offset is bisected between the first
+/// message of the stream and the last chunk: it is the first offset holding a message of `slot`
+/// or later. Some later messages are still of earlier slots, like the finalized updates of earlier
+/// slots, consumers skip them with the `slot` header. If the stream only holds earlier slots,
+/// consuming starts at the last chunk.
+pub async fn slot_offset<P: StreamProbe>(
+    probe: &mut P,
+    slot: Slot,
+) -> anyhow::Result<StreamOffset> {
+    let Some((first_offset, first_slot)) = probe.probe(StreamOffset::First).await? else {
+        // empty stream, everything published from now on is wanted
+        return Ok(StreamOffset::First);
+    };
+    if first_slot >= slot {
+        return Ok(StreamOffset::Offset(first_offset));
+    }
+    let Some((last_offset, last_slot)) = probe.probe(StreamOffset::Last).await? else {
+        return Ok(StreamOffset::Offset(first_offset));
+    };
+    if last_slot < slot {
+        return Ok(StreamOffset::Offset(last_offset));
+    }
+
+    // the message at low is before the slot, the one at high is not
+    let (mut low, mut high) = (first_offset, last_offset);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        match probe.probe(StreamOffset::Offset(middle)).await? {
+            Some((offset, message_slot)) if offset < high => {
+                if message_slot >= slot {
+                    high = offset;
+                } else {
+                    low = offset;
+                }
+            }
+            // no message left between middle and high
+            _ => low = middle,
+        }
+    }
+    Ok(StreamOffset::Offset(high))
+}
+
+/// `StreamProbe` consuming one message of a stream queue at a time, on its own channel.
+pub struct AmqpStreamProbe {
+    channel: Channel,
+    queue: String,
+    // an empty stream, or an offset past its end, delivers nothing
+    timeout: Duration,
+    probes: u64,
+}
+
+impl AmqpStreamProbe {
+    pub async fn new(
+        connection: &Connection,
+        queue: &str,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let channel = connection.create_channel().await?;
+        // stream consumers need a prefetch, one message is all a probe reads
+        channel.basic_qos(1, BasicQosOptions::default()).await?;
+        Ok(Self {
+            channel,
+            queue: queue.to_string(),
+            timeout,
+            probes: 0,
+        })
+    }
+
+    pub async fn close(self) -> anyhow::Result<()> {
+        self.channel
+            .close(REPLY_SUCCESS, "stream probe done")
+            .await?;
+        Ok(())
+    }
+}
+
+impl StreamProbe for AmqpStreamProbe {
+    async fn probe(&mut self, offset: StreamOffset) -> anyhow::Result<Option<(u64, Slot)>> {
+        self.probes += 1;
+        let consumer_tag = format!("quic-geyser-stream-probe-{}", self.probes);
+        let mut consumer = self
+            .channel
+            .basic_consume(
+                &self.queue,
+                &consumer_tag,
+                BasicConsumeOptions::default(),
+                offset.consume_arguments(),
+            )
+            .await
+            .with_context(|| format!("consuming stream {} at {offset:?}", self.queue))?;
+        let delivery = tokio::time::timeout(self.timeout, consumer.next()).await;
+        self.channel
+            .basic_cancel(&consumer_tag, BasicCancelOptions::default())
+            .await?;
+
+        let delivery = match delivery {
+            Ok(Some(delivery)) => delivery?,
+            Ok(None) | Err(_) => return Ok(None),
+        };
+        delivery.acker.ack(BasicAckOptions::default()).await?;
+        let header = |name: &str| {
+            delivery
+                .properties
+                .headers()
+                .as_ref()
+                .and_then(|headers| headers.inner().iter().find(|(key, _)| key.as_str() == name))
+                .and_then(|(_, value)| integer(value))
+        };
+        let offset = header(STREAM_OFFSET_ARGUMENT)
+            .with_context(|| format!("message of {} without stream offset", self.queue))?;
+        let slot = header(SLOT_HEADER)
+            .with_context(|| format!("message of {} without slot header", self.queue))?;
+        Ok(Some((offset as u64, slot as Slot)))
+    }
+}
+
+fn integer(value: &AMQPValue) -> Option<i64> {
+    match *value {
+        AMQPValue::ShortShortInt(value) => Some(value.into()),
+        AMQPValue::ShortShortUInt(value) => Some(value.into()),
+        AMQPValue::ShortInt(value) => Some(value.into()),
+        AMQPValue::ShortUInt(value) => Some(value.into()),
+        AMQPValue::LongInt(value) => Some(value.into()),
+        AMQPValue::LongUInt(value) => Some(value.into()),
+        AMQPValue::LongLongInt(value) => Some(value),
+        _ => None,
+    }
+}
@@ -0,0 +1,240 @@
+use lapin::{
+    acker::Acker,
+    message::Delivery as AmqpDelivery,
+    types::{AMQPValue, FieldTable, ShortString},
+    BasicProperties,
+};
+use quic_geyser_common::{
+    channel_message::{AccountData, ChannelMessage},
+    compression::CompressionType,
+    mq_payload::{
+        serialize, serialize_account, serialize_block, PayloadEncoding, ACCOUNT_MESSAGE_TYPE,
+        BINCODE_CONTENT_TYPE, BLOCK_MESSAGE_TYPE, BLOCK_META_MESSAGE_TYPE, ENTRY_MESSAGE_TYPE,
+        JSON_CONTENT_TYPE, LZ4_CONTENT_ENCODING, MESSAGE_TYPE_HEADER, PROTOBUF_CONTENT_TYPE,
+        SLOT_MESSAGE_TYPE,
+    },
+    protobuf,
+    types::{
+        block::Block,
+        block_meta::{BlockMeta, SlotMeta},
+        entry::Entry,
+    },
+};
+use quic_geyser_mq_consumer::consumer::{Delivery, UndecodableDelivery};
+use solana_sdk::{
+    account::Account, commitment_config::CommitmentConfig, hash::Hash, pubkey::Pubkey,
+};
+
+const ENCODINGS: [PayloadEncoding; 4] = [
+    PayloadEncoding::Json,
+    PayloadEncoding::JsonBase64,
+    PayloadEncoding::JsonBase58,
+    PayloadEncoding::Bincode,
+];
+
+fn block_meta() -> BlockMeta {
+    BlockMeta {
+        parent_slot: 9,
+        slot: 10,
+        parent_blockhash: "parent".to_string(),
+        blockhash: "blockhash".to_string(),
+        rewards: vec![],
+        block_height: Some(8),
+        executed_transaction_count: 0,
+        entries_count: 1,
+        block_time: 1_700_000_000,
+    }
+}
+
+/// Messages of every type with their message type header, serialized like the plugin does.
+fn payloads(encoding: PayloadEncoding) -> Vec<(ChannelMessage, &'static str, Vec<u8>)> {
+    let account_data = AccountData {
+        pubkey: Pubkey::new_unique(),
+        account: Account {
+            lamports: 42,
+            data: vec![1, 2, 3, 4],
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        },
+        write_version: 7,
+    };
+    let slot_meta = SlotMeta {
+        slot: 10,
+        parent: 9,
+        commitment_config: CommitmentConfig::confirmed(),
+    };
+    let entry = Entry {
+        slot: 10,
+        index: 0,
+        num_hashes: 12500,
+        hash: Hash::new_unique().to_string(),
+        executed_transaction_count: 0,
+        starting_transaction_index: 0,
+    };
+    let block = Block::build(block_meta(), vec![], vec![], CompressionType::Lz4Fast(8)).unwrap();
+    vec![
+        (
+            ChannelMessage::Account(account_data.clone(), 10, false),
+            ACCOUNT_MESSAGE_TYPE,
+            serialize_account(&account_data, 10, false, encoding).unwrap(),
+        ),
+        (
+            ChannelMessage::Slot(10, 9, CommitmentConfig::confirmed()),
+            SLOT_MESSAGE_TYPE,
+            serialize(&slot_meta, encoding).unwrap(),
+        ),
+        (
+            ChannelMessage::BlockMeta(block_meta()),
+            BLOCK_META_MESSAGE_TYPE,
+            serialize(&block_meta(), encoding).unwrap(),
+        ),
+        (
+            ChannelMessage::Entry(entry.clone()),
+            ENTRY_MESSAGE_TYPE,
+            serialize(&entry, encoding).unwrap(),
+        ),
+        (
+            ChannelMessage::Block(block.clone()),
+            BLOCK_MESSAGE_TYPE,
+            serialize_block(&block, encoding).unwrap(),
+        ),
+    ]
+}
+
+fn content_type(encoding: PayloadEncoding) -> &'static str {
+    match encoding {
+        PayloadEncoding::Bincode => BINCODE_CONTENT_TYPE,
+        _ => JSON_CONTENT_TYPE,
+    }
+}
+
+/// Protobuf payload of a message of `payloads`.
+fn protobuf_data(message: &ChannelMessage) -> Vec<u8> {
+    match message {
+        ChannelMessage::Account(account_data, slot, is_startup) => {
+            protobuf::encode_account(account_data, *slot, *is_startup)
+        }
+        ChannelMessage::Slot(slot, parent, commitment_config) => protobuf::encode_slot(&SlotMeta {
+            slot: *slot,
+            parent: *parent,
+            commitment_config: *commitment_config,
+        }),
+        ChannelMessage::BlockMeta(block_meta) => protobuf::encode_block_meta(block_meta),
+        ChannelMessage::Entry(entry) => protobuf::encode_entry(entry),
+        ChannelMessage::Block(block) => protobuf::encode_block(block),
+        ChannelMessage::Transaction(transaction) => protobuf::encode_transaction(transaction),
+    }
+}
+
+/// Delivery of a message with its acker, which tells whether the message was acked or rejected.
+fn delivery(
+    message_type: &str,
+    content_type: &str,
+    content_encoding: Option<&str>,
+    data: Vec<u8>,
+) -> (AmqpDelivery, Acker) {
+    let mut headers = FieldTable::default();
+    headers.insert(
+        MESSAGE_TYPE_HEADER.into(),
+        AMQPValue::LongString(message_type.into()),
+    );
+    let mut properties = BasicProperties::default()
+        .with_content_type(ShortString::from(content_type))
+        .with_message_id(ShortString::from("10:0"))
+        .with_headers(headers);
+    if let Some(content_encoding) = content_encoding {
+        properties = properties.with_content_encoding(ShortString::from(content_encoding));
+    }
+    let acker = Acker::default();
+    let delivery = AmqpDelivery {
+        delivery_tag: 1,
+        exchange: ShortString::from(""),
+        routing_key: ShortString::from("queue"),
+        redelivered: false,
+        properties,
+        data,
+        acker: acker.clone(),
+    };
+    (delivery, acker)
+}
+
+#[tokio::test]
+async fn test_decodes_every_message_type_in_every_encoding() {
+    for encoding in ENCODINGS {
+        for (message, message_type, data) in payloads(encoding) {
+            let (amqp_delivery, acker) = delivery(message_type, content_type(encoding), None, data);
+            let delivery = Delivery::decode(amqp_delivery).await.unwrap();
+            assert_eq!(delivery.message, message, "{message_type} {encoding:?}");
+            assert_eq!(delivery.message_id.as_deref(), Some("10:0"));
+            // left for the consumer to ack
+            assert!(!acker.used());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_decodes_compressed_payloads() {
+    for encoding in [PayloadEncoding::Json, PayloadEncoding::Bincode] {
+        for (message, message_type, data) in payloads(encoding) {
+            let compressed = CompressionType::Lz4Fast(8).compress(&data);
+            let (amqp_delivery, _) = delivery(
+                message_type,
+                content_type(encoding),
+                Some(LZ4_CONTENT_ENCODING),
+                compressed,
+            );
+            let delivery = Delivery::decode(amqp_delivery).await.unwrap();
+            assert_eq!(delivery.message, message, "{message_type} {encoding:?}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_decodes_protobuf_payloads() {
+    for (message, message_type, _) in payloads(PayloadEncoding::Bincode) {
+        let content_type = format!(
+            "{PROTOBUF_CONTENT_TYPE}; messageType={}.{message_type}",
+            protobuf::PACKAGE
+        );
+        let (amqp_delivery, acker) =
+            delivery(message_type, &content_type, None, protobuf_data(&message));
+        let delivery = Delivery::decode(amqp_delivery).await.unwrap();
+        assert_eq!(delivery.message, message, "{message_type}");
+        assert!(!acker.used());
+    }
+}
+
+#[tokio::test]
+async fn test_undecodable_messages_are_left_unacked() {
+    let (amqp_delivery, acker) = delivery(
+        ACCOUNT_MESSAGE_TYPE,
+        JSON_CONTENT_TYPE,
+        None,
+        b"{ not json".to_vec(),
+    );
+    let error = Delivery::decode(amqp_delivery).await.unwrap_err();
+    assert!(format!("{error:#}").contains("undecodable AccountUpdate message"));
+    // handed back so the consumer can requeue, reject or ack it
+    let undecodable = error.downcast::<UndecodableDelivery>().unwrap();
+    assert_eq!(undecodable.delivery.data, b"{ not json");
+    assert!(!acker.used());
+
+    let (amqp_delivery, acker) = delivery(
+        ACCOUNT_MESSAGE_TYPE,
+        "application/x-protobuf; messageType=quic_geyser.mq.AccountUpdate",
+        None,
+        vec![0x0a, 32],
+    );
+    assert!(Delivery::decode(amqp_delivery).await.is_err());
+    assert!(!acker.used());
+
+    let (amqp_delivery, acker) = delivery(
+        SLOT_MESSAGE_TYPE,
+        JSON_CONTENT_TYPE,
+        Some("gzip"),
+        b"{}".to_vec(),
+    );
+    assert!(Delivery::decode(amqp_delivery).await.is_err());
+    assert!(!acker.used());
+}
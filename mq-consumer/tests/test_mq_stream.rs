@@ -0,0 +1,87 @@
+use lapin::types::{AMQPValue, FieldTable};
+use quic_geyser_mq_consumer::mq_stream::{slot_offset, StreamOffset, StreamProbe};
+use solana_sdk::clock::Slot;
+
+/// Stream holding the slots of its messages, from `first_offset` on.
+struct MemoryStream {
+    first_offset: u64,
+    slots: Vec<Slot>,
+    chunk_size: u64,
+    probes: usize,
+}
+
+impl MemoryStream {
+    fn new(first_offset: u64, slots: Vec<Slot>) -> Self {
+        Self {
+            first_offset,
+            slots,
+            chunk_size: 4,
+            probes: 0,
+        }
+    }
+}
+
+impl StreamProbe for MemoryStream {
+    async fn probe(&mut self, offset: StreamOffset) -> anyhow::Result<Option<(u64, Slot)>> {
+        self.probes += 1;
+        let end = self.first_offset + self.slots.len() as u64;
+        let offset = match offset {
+            StreamOffset::First => self.first_offset,
+            // first message of the last chunk
+            StreamOffset::Last => {
+                let last = end.saturating_sub(1).max(self.first_offset);
+                last - last % self.chunk_size
+            }
+            StreamOffset::Offset(offset) => offset.max(self.first_offset),
+            StreamOffset::Next | StreamOffset::Timestamp(_) => unimplemented!(),
+        };
+        Ok(self
+            .slots
+            .get((offset - self.first_offset) as usize)
+            .map(|slot| (offset, *slot)))
+    }
+}
+
+fn offset_of(stream: &mut MemoryStream, slot: Slot) -> StreamOffset {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(slot_offset(stream, slot))
+        .unwrap()
+}
+
+#[test]
+fn test_slot_offset_is_the_first_message_of_the_slot() {
+    // retention removed offsets before 100, slot 13 has no message
+    let slots = vec![10, 10, 11, 11, 11, 12, 14, 14, 15, 16, 16, 17, 18];
+    let mut stream = MemoryStream::new(100, slots);
+
+    assert_eq!(offset_of(&mut stream, 11), StreamOffset::Offset(102));
+    assert_eq!(offset_of(&mut stream, 12), StreamOffset::Offset(105));
+    assert_eq!(offset_of(&mut stream, 13), StreamOffset::Offset(106));
+    assert_eq!(offset_of(&mut stream, 17), StreamOffset::Offset(111));
+
+    stream.probes = 0;
+    assert_eq!(offset_of(&mut stream, 15), StreamOffset::Offset(108));
+    // first, last and a bisection of the 12 offsets between them
+    assert!(stream.probes <= 6, "{} probes", stream.probes);
+}
+
+#[test]
+fn test_slot_offset_outside_of_the_stream() {
+    let mut stream = MemoryStream::new(100, vec![10, 11, 12, 13, 14, 15, 16]);
+    // older slots were removed by retention, replay from the oldest message
+    assert_eq!(offset_of(&mut stream, 5), StreamOffset::Offset(100));
+    // not published yet, start with the last chunk
+    assert_eq!(offset_of(&mut stream, 20), StreamOffset::Offset(104));
+
+    let mut empty = MemoryStream::new(0, vec![]);
+    assert_eq!(offset_of(&mut empty, 10), StreamOffset::First);
+}
+
+#[test]
+fn test_stream_offset_consume_arguments() {
+    let mut expected = FieldTable::default();
+    expected.insert("x-stream-offset".into(), AMQPValue::LongLongInt(42));
+    assert_eq!(StreamOffset::Offset(42).consume_arguments(), expected);
+}
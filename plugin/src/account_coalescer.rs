@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, HashMap};
+
+use prometheus::{opts, register_int_counter, IntCounter};
+use quic_geyser_common::channel_message::{AccountData, ChannelMessage};
+use solana_sdk::{clock::Slot, commitment_config::CommitmentLevel, pubkey::Pubkey};
+
+use crate::{commitment_buffer::reaches, config::ConfigCoalesce};
+
+lazy_static::lazy_static! {
+    static ref MQ_ACCOUNTS_COALESCED: IntCounter =
+       register_int_counter!(opts!("quic_plugin_mq_accounts_coalesced", "Number of account updates replaced by a later write of the same account in the same slot")).unwrap();
+}
+
+/// Keeps only the last write of every account in a slot, like the block builder does
+/// for the accounts of a block.
+///
+/// Without a flush commitment, the updates of a slot are released as soon as an event of a later
+/// slot shows up. With one, they are released when the slot reaches it, with every earlier slot.
+/// Updates of a slot which was already released go out directly.
+pub struct AccountCoalescer {
+    flush_commitment: Option<CommitmentLevel>,
+    slots: BTreeMap<Slot, HashMap<Pubkey, AccountData>>,
+    // last slot released, later updates of it or of earlier slots are not held
+    released_up_to: Option<Slot>,
+}
+
+impl AccountCoalescer {
+    pub fn new(config: &ConfigCoalesce) -> Self {
+        Self {
+            flush_commitment: config.flush_commitment,
+            slots: BTreeMap::new(),
+            released_up_to: None,
+        }
+    }
+
+    /// Number of account updates held.
+    pub fn len(&self) -> usize {
+        self.slots.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the messages which can be published now.
+    pub fn push(&mut self, message: ChannelMessage) -> Vec<ChannelMessage> {
+        let slot = match &message {
+            // startup accounts are written once
+            ChannelMessage::Account(_, _, true) => return vec![message],
+            ChannelMessage::Account(_, slot, false) => *slot,
+            ChannelMessage::Slot(slot, _, commitment_config) => {
+                let mut ready = match self.flush_commitment {
+                    None => self.release_before(*slot),
+                    Some(flush_commitment)
+                        if reaches(commitment_config.commitment, flush_commitment) =>
+                    {
+                        self.release_before(*slot + 1)
+                    }
+                    Some(_) => vec![],
+                };
+                ready.push(message);
+                return ready;
+            }
+            ChannelMessage::Transaction(transaction) => transaction.slot_identifier.slot,
+            ChannelMessage::BlockMeta(block_meta) => block_meta.slot,
+            ChannelMessage::Block(block) => block.meta.slot,
+            ChannelMessage::Entry(entry) => entry.slot,
+        };
+
+        let mut ready = match self.flush_commitment {
+            None => self.release_before(slot),
+            Some(_) => vec![],
+        };
+        let account_data = match message {
+            ChannelMessage::Account(account_data, _, _) => account_data,
+            message => {
+                ready.push(message);
+                return ready;
+            }
+        };
+        if self.released_up_to.is_some_and(|released| slot <= released) {
+            ready.push(ChannelMessage::Account(account_data, slot, false));
+            return ready;
+        }
+        let accounts = self.slots.entry(slot).or_default();
+        match accounts.get(&account_data.pubkey) {
+            Some(held) => {
+                MQ_ACCOUNTS_COALESCED.inc();
+                if held.write_version <= account_data.write_version {
+                    accounts.insert(account_data.pubkey, account_data);
+                }
+            }
+            None => {
+                accounts.insert(account_data.pubkey, account_data);
+            }
+        }
+        ready
+    }
+
+    /// Releases every held update, when the plugin stops.
+    pub fn flush(&mut self) -> Vec<ChannelMessage> {
+        match self.slots.last_key_value() {
+            Some((&last_slot, _)) => self.release_before(last_slot + 1),
+            None => vec![],
+        }
+    }
+
+    fn release_before(&mut self, slot: Slot) -> Vec<ChannelMessage> {
+        let kept = self.slots.split_off(&slot);
+        let released = std::mem::replace(&mut self.slots, kept);
+        let mut ready = vec![];
+        for (slot, accounts) in released {
+            let mut accounts = accounts.into_values().collect::<Vec<_>>();
+            accounts.sort_by_key(|account_data| account_data.write_version);
+            ready.extend(
+                accounts
+                    .into_iter()
+                    .map(|account_data| ChannelMessage::Account(account_data, slot, false)),
+            );
+        }
+        let released_up_to = slot.saturating_sub(1);
+        self.released_up_to = Some(
+            self.released_up_to
+                .map_or(released_up_to, |released| released.max(released_up_to)),
+        );
+        ready
+    }
+}
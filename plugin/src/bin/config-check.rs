@@ -1,4 +1,7 @@
-use {clap::Parser, quic_geyser_plugin::config::Config};
+use {
+    clap::Parser,
+    quic_geyser_plugin::{config::Config, filters::PluginFilters},
+};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
@@ -10,7 +13,9 @@ struct Args {
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let _config = Config::load_from_file(args.config)?;
+    let config = Config::load_from_file(args.config)?;
+    config.required_broker_urls()?;
+    PluginFilters::new(&config)?;
     println!("Config is OK!");
     Ok(())
 }
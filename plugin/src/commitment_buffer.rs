@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use quic_geyser_common::channel_message::ChannelMessage;
+use solana_sdk::{clock::Slot, commitment_config::CommitmentLevel};
+
+// released slots remembered behind the last root, so late events of those slots still go out
+const RELEASED_SLOTS_KEPT_BEHIND_ROOT: u64 = 1024;
+
+#[derive(Default)]
+struct SlotBuffer {
+    parent: Option<Slot>,
+    messages: Vec<ChannelMessage>,
+}
+
+/// Holds the events of a slot until the slot reaches the configured commitment.
+///
+/// Slot updates are never held back, they are released right after the events they unlock.
+/// A slot reaching the commitment also releases its ancestors still held.
+/// Slots still held when a later slot is rooted were on a dead fork, their events are discarded.
+pub struct CommitmentBuffer {
+    commitment: CommitmentLevel,
+    slots: BTreeMap<Slot, SlotBuffer>,
+    released: BTreeSet<Slot>,
+    last_root: Option<Slot>,
+}
+
+impl CommitmentBuffer {
+    pub fn new(commitment: CommitmentLevel) -> Self {
+        Self {
+            commitment,
+            slots: BTreeMap::new(),
+            released: BTreeSet::new(),
+            last_root: None,
+        }
+    }
+
+    pub fn commitment(&self) -> CommitmentLevel {
+        self.commitment
+    }
+
+    /// Number of events held.
+    pub fn len(&self) -> usize {
+        self.slots
+            .values()
+            .map(|buffer| buffer.messages.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the messages which can be published now, oldest slots first.
+    pub fn push(&mut self, message: ChannelMessage) -> Vec<ChannelMessage> {
+        if self.commitment == CommitmentLevel::Processed {
+            return vec![message];
+        }
+
+        let (slot, parent) = match &message {
+            ChannelMessage::Slot(slot, parent, commitment_config) => {
+                let (slot, commitment) = (*slot, commitment_config.commitment);
+                if !self.is_decided(slot) && !self.released.contains(&slot) {
+                    self.slots.entry(slot).or_default().parent = Some(*parent);
+                }
+                let mut ready = vec![];
+                if reaches(commitment, self.commitment) {
+                    self.release(slot, &mut ready);
+                }
+                if commitment == CommitmentLevel::Finalized {
+                    self.discard_before(slot);
+                }
+                ready.push(message);
+                return ready;
+            }
+            // startup accounts come from the snapshot, which is rooted
+            ChannelMessage::Account(_, _, true) => return vec![message],
+            ChannelMessage::Account(_, slot, false) => (*slot, None),
+            ChannelMessage::Transaction(transaction) => (transaction.slot_identifier.slot, None),
+            ChannelMessage::BlockMeta(block_meta) => {
+                (block_meta.slot, Some(block_meta.parent_slot))
+            }
+            ChannelMessage::Block(block) => (block.meta.slot, Some(block.meta.parent_slot)),
+            ChannelMessage::Entry(entry) => (entry.slot, None),
+        };
+
+        if self.released.contains(&slot) {
+            return vec![message];
+        }
+        if self.is_decided(slot) {
+            log::debug!(
+                "Discarding MQ message of slot {slot} which is behind the last root and was never {}",
+                self.commitment
+            );
+            return vec![];
+        }
+        let buffer = self.slots.entry(slot).or_default();
+        if buffer.parent.is_none() {
+            buffer.parent = parent;
+        }
+        buffer.messages.push(message);
+        vec![]
+    }
+
+    /// Hands out every held event, oldest slots first, when the plugin stops and the slots will
+    /// not reach the commitment in this buffer anymore.
+    pub fn flush(&mut self) -> Vec<ChannelMessage> {
+        std::mem::take(&mut self.slots)
+            .into_values()
+            .flat_map(|buffer| buffer.messages)
+            .collect()
+    }
+
+    // slots at or behind the last root are either released or on a dead fork
+    fn is_decided(&self, slot: Slot) -> bool {
+        self.last_root.is_some_and(|root| slot <= root)
+    }
+
+    fn release(&mut self, slot: Slot, ready: &mut Vec<ChannelMessage>) {
+        let mut chain = vec![];
+        let mut next = Some(slot);
+        while let Some(slot) = next.take() {
+            if self.is_decided(slot) || !self.released.insert(slot) {
+                break;
+            }
+            if let Some(buffer) = self.slots.remove(&slot) {
+                next = buffer.parent.filter(|parent| *parent < slot);
+                chain.push(buffer.messages);
+            }
+        }
+        ready.extend(chain.into_iter().rev().flatten());
+    }
+
+    fn discard_before(&mut self, root: Slot) {
+        let kept = self.slots.split_off(&root);
+        for (slot, buffer) in std::mem::replace(&mut self.slots, kept) {
+            if !buffer.messages.is_empty() {
+                log::warn!(
+                    "Discarding {} MQ messages of slot {slot} which was never {} before slot {root} was rooted",
+                    buffer.messages.len(),
+                    self.commitment
+                );
+            }
+        }
+        self.released = self
+            .released
+            .split_off(&root.saturating_sub(RELEASED_SLOTS_KEPT_BEHIND_ROOT));
+        self.last_root = Some(self.last_root.map_or(root, |last_root| last_root.max(root)));
+    }
+}
+
+/// Whether a slot at `commitment` has reached the `target` commitment.
+pub fn reaches(commitment: CommitmentLevel, target: CommitmentLevel) -> bool {
+    match target {
+        CommitmentLevel::Processed => true,
+        CommitmentLevel::Confirmed => commitment != CommitmentLevel::Processed,
+        CommitmentLevel::Finalized => commitment == CommitmentLevel::Finalized,
+    }
+}
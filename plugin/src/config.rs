@@ -1,19 +1,54 @@
-use std::{fs::read_to_string, path::Path};
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 use agave_geyser_plugin_interface::geyser_plugin_interface::GeyserPluginError;
-use quic_geyser_common::config::ConfigQuicPlugin;
+use quic_geyser_common::{
+    compression::CompressionType,
+    config::{default_true, ConfigQuicPlugin},
+};
+pub use quic_geyser_common::mq_payload::PayloadEncoding;
 use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentLevel;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     pub libpath: String,
+    #[serde(default)]
     pub amqp_url: String,
+    /// Brokers tried in turn when the connection fails, after `amqp_url` if it is set.
+    #[serde(default)]
+    pub amqp_urls: Vec<String>,
     pub quic_plugin: ConfigQuicPlugin,
-    // New fields:
+    // filters of the MQ output, see `ConfigFilterScope`
     #[serde(default)]
     pub account_update_pubkeys: Vec<String>,
     #[serde(default)]
     pub transaction_pubkeys: Vec<String>,
+    #[serde(default)]
+    pub account_filters: Vec<ConfigAccountFilter>,
+    #[serde(default)]
+    pub transaction_filters: Vec<ConfigTransactionFilter>,
+    /// Filters of the events sent to QUIC subscribers, which get every event if not set.
+    #[serde(default)]
+    pub quic_filters: Option<ConfigFilterScope>,
+    /// Filters of the events the block builder gets, it gets every event if not set.
+    /// Blocks built from filtered transactions do not match their block meta.
+    #[serde(default)]
+    pub block_builder_filters: Option<ConfigFilterScope>,
+    /// Failed and vote transactions each output gets, before its filters apply.
+    #[serde(default)]
+    pub transaction_policies: ConfigTransactionPolicies,
+    /// Asks the validator for entry notifications, sent to every output.
+    #[serde(default)]
+    pub entry_notifications: bool,
+    /// Reloads the filters and transaction policies when the config file changes,
+    /// disabled if not set.
+    #[serde(default)]
+    pub filters_reload: Option<ConfigFiltersReload>,
+    #[serde(default)]
+    pub mq: ConfigMq,
 }
 
 impl Config {
@@ -27,6 +62,46 @@ impl Config {
         let config = read_to_string(file).map_err(GeyserPluginError::ConfigFileOpenError)?;
         Self::load_from_str(&config)
     }
+
+    /// Filters of the MQ output, set at the top level of the config.
+    pub fn mq_filters(&self) -> ConfigFilterScope {
+        ConfigFilterScope {
+            account_update_pubkeys: self.account_update_pubkeys.clone(),
+            transaction_pubkeys: self.transaction_pubkeys.clone(),
+            account_filters: self.account_filters.clone(),
+            transaction_filters: self.transaction_filters.clone(),
+        }
+    }
+
+    /// Broker urls in failover order, the `AMQP_URL` environment variable
+    /// (comma separated) replaces the ones of the config.
+    pub fn broker_urls(&self) -> Vec<String> {
+        let urls = match std::env::var("AMQP_URL") {
+            Ok(env_urls) => env_urls.split(',').map(str::to_string).collect(),
+            Err(_) => std::iter::once(self.amqp_url.clone())
+                .chain(self.amqp_urls.iter().cloned())
+                .collect::<Vec<_>>(),
+        };
+        let mut broker_urls: Vec<String> = vec![];
+        for url in urls {
+            let url = url.trim();
+            if !url.is_empty() && !broker_urls.iter().any(|known| known == url) {
+                broker_urls.push(url.to_string());
+            }
+        }
+        broker_urls
+    }
+
+    /// `broker_urls`, failing when none is configured as the MQ output needs one.
+    pub fn required_broker_urls(&self) -> std::result::Result<Vec<String>, GeyserPluginError> {
+        let broker_urls = self.broker_urls();
+        if broker_urls.is_empty() {
+            return Err(GeyserPluginError::ConfigFileReadError {
+                msg: "no AMQP broker configured, set amqp_url, amqp_urls or AMQP_URL".to_string(),
+            });
+        }
+        Ok(broker_urls)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,3 +121,695 @@ impl RpcServiceConfig {
         10801
     }
 }
+
+/// Account updates and transactions one output of the plugin gets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFilterScope {
+    /// Owners of the account updates.
+    #[serde(default)]
+    pub account_update_pubkeys: Vec<String>,
+    /// Transactions using any of these accounts.
+    #[serde(default)]
+    pub transaction_pubkeys: Vec<String>,
+    /// Account updates matching any of these filters, besides the ones of `account_update_pubkeys`.
+    #[serde(default)]
+    pub account_filters: Vec<ConfigAccountFilter>,
+    /// Transactions matching any of these filters, besides the ones of `transaction_pubkeys`.
+    #[serde(default)]
+    pub transaction_filters: Vec<ConfigTransactionFilter>,
+}
+
+/// The filters and `transaction_policies` are only swapped once they are all valid, otherwise
+/// the active ones are kept and the error is logged. Other settings are read when the plugin loads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFiltersReload {
+    /// How often the modification time of the config file is checked.
+    #[serde(default = "ConfigFiltersReload::default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+impl ConfigFiltersReload {
+    pub fn default_poll_interval_ms() -> u64 {
+        1_000
+    }
+}
+
+impl Default for ConfigFiltersReload {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: Self::default_poll_interval_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTransactionPolicies {
+    #[serde(default)]
+    pub quic: ConfigTransactionPolicy,
+    #[serde(default)]
+    pub mq: ConfigTransactionPolicy,
+    /// Keeps every transaction by default, blocks are only complete once they have
+    /// `executed_transaction_count` transactions, failed ones included.
+    #[serde(default = "ConfigTransactionPolicy::all")]
+    pub block_builder: ConfigTransactionPolicy,
+}
+
+impl Default for ConfigTransactionPolicies {
+    fn default() -> Self {
+        Self {
+            quic: ConfigTransactionPolicy::default(),
+            mq: ConfigTransactionPolicy::default(),
+            block_builder: ConfigTransactionPolicy::all(),
+        }
+    }
+}
+
+/// By default failed transactions are dropped and vote transactions are kept, except for the
+/// block builder which keeps both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTransactionPolicy {
+    /// Sends failed transactions, with their error.
+    #[serde(default)]
+    pub failed: bool,
+    #[serde(default = "default_true")]
+    pub vote: bool,
+}
+
+impl Default for ConfigTransactionPolicy {
+    fn default() -> Self {
+        Self {
+            failed: false,
+            vote: true,
+        }
+    }
+}
+
+impl ConfigTransactionPolicy {
+    pub fn all() -> Self {
+        Self {
+            failed: true,
+            vote: true,
+        }
+    }
+
+    pub fn allows(&self, is_vote: bool, failed: bool) -> bool {
+        (self.vote || !is_vote) && (self.failed || !failed)
+    }
+}
+
+/// Config form of `quic_geyser_common::filters::AccountFilter`, with base58 pubkeys.
+///
+/// An update of `owner` matches when its data matches all of `filters`, an update of another
+/// owner when its pubkey is one of `accounts`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigAccountFilter {
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    #[serde(default)]
+    pub filters: Vec<ConfigAccountDataFilter>,
+}
+
+/// Config form of `quic_geyser_common::filters::TransactionFilter`, with base58 pubkeys.
+///
+/// A transaction matches when it uses one of `account_include` (if any), all of
+/// `account_required` and none of `account_exclude`. With `signer_only` or `writable_only`,
+/// included accounts only count when they sign or are writable in the transaction.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTransactionFilter {
+    #[serde(default)]
+    pub account_include: Vec<String>,
+    #[serde(default)]
+    pub account_required: Vec<String>,
+    #[serde(default)]
+    pub account_exclude: Vec<String>,
+    #[serde(default)]
+    pub signer_only: bool,
+    #[serde(default)]
+    pub writable_only: bool,
+    /// Only vote or only non vote transactions, both if not set.
+    #[serde(default)]
+    pub vote: Option<bool>,
+    /// Only failed or only successful transactions, both if not set.
+    #[serde(default)]
+    pub failed: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigAccountDataFilter {
+    /// Exact length of the account data.
+    Datasize(u64),
+    Memcmp(ConfigMemcmp),
+}
+
+/// Bytes the account data holds at `offset`, like the RPC `memcmp` filter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigMemcmp {
+    pub offset: u64,
+    pub bytes: String,
+    #[serde(default)]
+    pub encoding: MemcmpEncoding,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemcmpEncoding {
+    #[default]
+    Base58,
+    Base64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigMq {
+    #[serde(default)]
+    pub spool: ConfigSpool,
+    #[serde(default)]
+    pub topology: ConfigTopology,
+    /// Number of published messages waiting for a broker confirm at the same time.
+    #[serde(default = "ConfigMq::default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// Messages which are not confirmed after this delay are published again.
+    #[serde(default = "ConfigMq::default_confirm_timeout_ms")]
+    pub confirm_timeout_ms: u64,
+    #[serde(default)]
+    pub resend: ConfigResend,
+    #[serde(default)]
+    pub encoding: PayloadEncoding,
+    /// Compresses the payloads, disabled if not set.
+    #[serde(default)]
+    pub compression: Option<ConfigCompression>,
+    /// Events of a slot are held until the slot reaches this commitment,
+    /// and discarded if the slot is on a fork which never gets there.
+    /// Slot updates are never held, events still held on unload are spooled as `processed`.
+    #[serde(default = "ConfigMq::default_commitment")]
+    pub commitment: CommitmentLevel,
+    /// Publishes only the last write of an account in a slot, disabled if not set.
+    #[serde(default)]
+    pub coalesce_accounts: Option<ConfigCoalesce>,
+    #[serde(default)]
+    pub reconnect: ConfigReconnect,
+    #[serde(default)]
+    pub channel: ConfigChannel,
+    /// On unload, how long the MQ loop keeps publishing what is left before it stops.
+    /// Messages which are not confirmed by then stay in the spool if it has a directory.
+    #[serde(default = "ConfigMq::default_shutdown_timeout_ms")]
+    pub shutdown_timeout_ms: u64,
+}
+
+impl ConfigMq {
+    pub fn default_max_in_flight() -> usize {
+        1024
+    }
+    pub fn default_confirm_timeout_ms() -> u64 {
+        30_000
+    }
+    pub fn default_commitment() -> CommitmentLevel {
+        CommitmentLevel::Processed
+    }
+    pub fn default_shutdown_timeout_ms() -> u64 {
+        10_000
+    }
+}
+
+impl Default for ConfigMq {
+    fn default() -> Self {
+        Self {
+            spool: ConfigSpool::default(),
+            topology: ConfigTopology::default(),
+            max_in_flight: Self::default_max_in_flight(),
+            confirm_timeout_ms: Self::default_confirm_timeout_ms(),
+            resend: ConfigResend::default(),
+            encoding: PayloadEncoding::default(),
+            compression: None,
+            commitment: Self::default_commitment(),
+            coalesce_accounts: None,
+            reconnect: ConfigReconnect::default(),
+            channel: ConfigChannel::default(),
+            shutdown_timeout_ms: Self::default_shutdown_timeout_ms(),
+        }
+    }
+}
+
+/// Compression of the MQ payloads, compressed messages have an `lz4` AMQP `content_encoding`:
+/// an lz4 block prefixed with its uncompressed size, as read by `lz4::block::decompress(data, None)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigCompression {
+    #[serde(default)]
+    pub compression_type: CompressionType,
+    /// Smaller payloads, and payloads which do not shrink, are published uncompressed.
+    #[serde(default = "ConfigCompression::default_min_size")]
+    pub min_size: usize,
+}
+
+impl ConfigCompression {
+    pub fn default_min_size() -> usize {
+        512
+    }
+}
+
+impl Default for ConfigCompression {
+    fn default() -> Self {
+        Self {
+            compression_type: CompressionType::default(),
+            min_size: Self::default_min_size(),
+        }
+    }
+}
+
+/// Only the write with the highest `write_version` of an account in a slot is published, after
+/// the other events of the slot. Late writes of a released slot are published as they come.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigCoalesce {
+    /// Account updates of a slot are released once the slot reaches this commitment.
+    /// If not set, they are released as soon as a later slot starts.
+    #[serde(default)]
+    pub flush_commitment: Option<CommitmentLevel>,
+}
+
+/// Channel between the geyser callbacks and the MQ loop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigChannel {
+    /// Number of messages the channel holds before the overflow policy applies.
+    #[serde(default = "ConfigChannel::default_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+impl ConfigChannel {
+    pub fn default_capacity() -> usize {
+        100_000
+    }
+}
+
+impl Default for ConfigChannel {
+    fn default() -> Self {
+        Self {
+            capacity: Self::default_capacity(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// What happens to a message sent while the MQ channel is full.
+/// Only `Block` and `ShedAccounts` can make the validator wait on the MQ loop.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// The geyser callback waits until the MQ loop makes room.
+    Block,
+    /// The message is dropped.
+    #[default]
+    DropNewest,
+    /// The oldest message of the channel is dropped to make room.
+    DropOldest,
+    /// The oldest account update of the channel is dropped to make room, or the message
+    /// if it is an account update itself. Other messages wait like with `Block` when the
+    /// channel holds no account update.
+    ShedAccounts,
+}
+
+/// Delay before connecting again after a broker failure, doubled on every failure up to
+/// `max_delay_ms`, with a random jitter of up to half the delay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigReconnect {
+    #[serde(default = "ConfigReconnect::default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "ConfigReconnect::default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl ConfigReconnect {
+    pub fn default_initial_delay_ms() -> u64 {
+        500
+    }
+    pub fn default_max_delay_ms() -> u64 {
+        30_000
+    }
+}
+
+impl Default for ConfigReconnect {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: Self::default_initial_delay_ms(),
+            max_delay_ms: Self::default_max_delay_ms(),
+        }
+    }
+}
+
+/// Delay before publishing a nacked or timed out message again, doubled on every failure of the
+/// message up to `max_delay_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigResend {
+    #[serde(default = "ConfigResend::default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "ConfigResend::default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+impl ConfigResend {
+    pub fn default_initial_delay_ms() -> u64 {
+        100
+    }
+    pub fn default_max_delay_ms() -> u64 {
+        10_000
+    }
+}
+
+impl Default for ConfigResend {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: Self::default_initial_delay_ms(),
+            max_delay_ms: Self::default_max_delay_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigSpool {
+    /// Absolute path of the directory where messages are kept until the broker confirms them,
+    /// created when the plugin loads. Without it the spool is kept in memory and unconfirmed
+    /// messages are lost when the plugin stops.
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+    #[serde(default = "ConfigSpool::default_segment_size")]
+    pub segment_size: u64,
+    /// Messages are dropped once the spool reaches this size.
+    #[serde(default = "ConfigSpool::default_max_size")]
+    pub max_size: u64,
+    /// Spooled messages are synced to disk at most this long after they are written, so a crash
+    /// of the host loses at most this much. 0 syncs every batch of messages.
+    #[serde(default = "ConfigSpool::default_sync_interval_ms")]
+    pub sync_interval_ms: u64,
+}
+
+impl ConfigSpool {
+    pub fn default_segment_size() -> u64 {
+        64 * 1024 * 1024 // 64 MBs
+    }
+    pub fn default_max_size() -> u64 {
+        4 * 1024 * 1024 * 1024 // 4 GBs
+    }
+    pub fn default_sync_interval_ms() -> u64 {
+        100
+    }
+}
+
+impl Default for ConfigSpool {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            segment_size: Self::default_segment_size(),
+            max_size: Self::default_max_size(),
+            sync_interval_ms: Self::default_sync_interval_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExchangeType {
+    #[default]
+    Direct,
+    Topic,
+    Fanout,
+}
+
+/// Sets `x-queue-type`, see `ConfigQueue::queue_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueType {
+    Classic,
+    Quorum,
+    Stream,
+}
+
+impl QueueType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueType::Classic => "classic",
+            QueueType::Quorum => "quorum",
+            QueueType::Stream => "stream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigTopology {
+    /// Exchange messages are published to, the default exchange "" routes by queue name.
+    #[serde(default)]
+    pub exchange: String,
+    #[serde(default)]
+    pub exchange_type: ExchangeType,
+    #[serde(default = "ConfigTopology::default_queues")]
+    pub queues: Vec<ConfigQueue>,
+    #[serde(default)]
+    pub routing_keys: ConfigRoutingKeys,
+    /// Poison messages are published to this exchange instead of being dropped, disabled if not set.
+    #[serde(default)]
+    pub dead_letter: Option<ConfigDeadLetter>,
+}
+
+impl ConfigTopology {
+    pub fn default_queues() -> Vec<ConfigQueue> {
+        [
+            ConfigRoutingKeys::default_transaction(),
+            ConfigRoutingKeys::default_account(),
+            ConfigRoutingKeys::default_block_meta(),
+            ConfigRoutingKeys::default_slot(),
+            ConfigRoutingKeys::default_block(),
+            ConfigRoutingKeys::default_entry(),
+        ]
+        .into_iter()
+        .map(ConfigQueue::durable)
+        .collect()
+    }
+}
+
+impl Default for ConfigTopology {
+    fn default() -> Self {
+        Self {
+            exchange: String::new(),
+            exchange_type: ExchangeType::default(),
+            queues: Self::default_queues(),
+            routing_keys: ConfigRoutingKeys::default(),
+            dead_letter: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigQueue {
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub durable: bool,
+    /// Routing keys binding the queue to the exchange, the queue name is used if empty.
+    /// Ignored for the default exchange.
+    #[serde(default)]
+    pub bindings: Vec<String>,
+    /// Sets `x-dead-letter-exchange`, messages rejected or expired by the broker go there.
+    #[serde(default)]
+    pub dead_letter_exchange: Option<String>,
+    /// Sets `x-dead-letter-routing-key`.
+    #[serde(default)]
+    pub dead_letter_routing_key: Option<String>,
+    /// Sets `x-max-length`.
+    #[serde(default)]
+    pub max_length: Option<i64>,
+    /// Sets `x-max-length-bytes`.
+    #[serde(default)]
+    pub max_length_bytes: Option<i64>,
+    /// Sets `x-overflow`: `drop-head`, `reject-publish` or `reject-publish-dlx`.
+    #[serde(default)]
+    pub overflow: Option<String>,
+    /// Sets `x-message-ttl`.
+    #[serde(default)]
+    pub message_ttl_ms: Option<i64>,
+    /// Sets `x-queue-type`. Stream queues keep their messages once consumed, until the retention
+    /// limits `max_length_bytes`, `max_age` and `stream_max_segment_size_bytes` remove them,
+    /// so consumers can replay them from an offset or a slot (see
+    /// `quic_geyser_mq_consumer::mq_stream::slot_offset`). They are always durable.
+    #[serde(default)]
+    pub queue_type: Option<QueueType>,
+    /// Sets `x-max-age` of stream queues, e.g. `1h` or `7D`.
+    #[serde(default)]
+    pub max_age: Option<String>,
+    /// Sets `x-stream-max-segment-size-bytes`, retention removes whole segments.
+    #[serde(default)]
+    pub stream_max_segment_size_bytes: Option<i64>,
+    /// Queue arguments passed as is to the broker, e.g. `"x-queue-type": "quorum"`.
+    /// The typed fields above take precedence.
+    #[serde(default)]
+    pub arguments: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ConfigQueue {
+    pub fn durable(name: String) -> Self {
+        Self {
+            name,
+            durable: true,
+            bindings: vec![],
+            dead_letter_exchange: None,
+            dead_letter_routing_key: None,
+            max_length: None,
+            max_length_bytes: None,
+            overflow: None,
+            message_ttl_ms: None,
+            queue_type: None,
+            max_age: None,
+            stream_max_segment_size_bytes: None,
+            arguments: serde_json::Map::new(),
+        }
+    }
+
+    pub fn is_stream(&self) -> bool {
+        self.queue_type == Some(QueueType::Stream)
+    }
+
+    /// Arguments of the queue declaration, raw arguments merged with the typed ones.
+    pub fn all_arguments(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut arguments = self.arguments.clone();
+        let typed: [(&str, Option<serde_json::Value>); 9] = [
+            ("x-dead-letter-exchange", self.dead_letter_exchange.clone().map(Into::into)),
+            ("x-dead-letter-routing-key", self.dead_letter_routing_key.clone().map(Into::into)),
+            ("x-max-length", self.max_length.map(Into::into)),
+            ("x-max-length-bytes", self.max_length_bytes.map(Into::into)),
+            ("x-overflow", self.overflow.clone().map(Into::into)),
+            ("x-message-ttl", self.message_ttl_ms.map(Into::into)),
+            ("x-queue-type", self.queue_type.map(|queue_type| queue_type.as_str().into())),
+            ("x-max-age", self.max_age.clone().map(Into::into)),
+            ("x-stream-max-segment-size-bytes", self.stream_max_segment_size_bytes.map(Into::into)),
+        ];
+        for (name, value) in typed {
+            if let Some(value) = value {
+                arguments.insert(name.to_string(), value);
+            }
+        }
+        arguments
+    }
+}
+
+/// Messages which cannot be serialized and messages nacked `max_nacks` times in a row keep their
+/// properties and get an `error`, `original_exchange` and `original_routing_key` header.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigDeadLetter {
+    #[serde(default = "ConfigDeadLetter::default_exchange")]
+    pub exchange: String,
+    #[serde(default = "ConfigDeadLetter::default_exchange_type")]
+    pub exchange_type: ExchangeType,
+    #[serde(default = "ConfigDeadLetter::default_queues")]
+    pub queues: Vec<ConfigQueue>,
+    #[serde(default = "ConfigDeadLetter::default_routing_key")]
+    pub routing_key: String,
+    /// A message nacked this many times in a row by the broker is dead-lettered.
+    #[serde(default = "ConfigDeadLetter::default_max_nacks")]
+    pub max_nacks: u32,
+}
+
+impl ConfigDeadLetter {
+    pub fn default_exchange() -> String {
+        "geyser.deadLetter".to_string()
+    }
+    pub fn default_exchange_type() -> ExchangeType {
+        ExchangeType::Fanout
+    }
+    pub fn default_queues() -> Vec<ConfigQueue> {
+        vec![ConfigQueue::durable("deadLetterDurable".to_string())]
+    }
+    pub fn default_routing_key() -> String {
+        "deadLetter".to_string()
+    }
+    pub fn default_max_nacks() -> u32 {
+        5
+    }
+}
+
+impl Default for ConfigDeadLetter {
+    fn default() -> Self {
+        Self {
+            exchange: Self::default_exchange(),
+            exchange_type: Self::default_exchange_type(),
+            queues: Self::default_queues(),
+            routing_key: Self::default_routing_key(),
+            max_nacks: Self::default_max_nacks(),
+        }
+    }
+}
+
+/// Routing key templates for each message type.
+/// Placeholders are replaced by the message values :
+/// `{slot}` for every message, `{signature}`, `{program_id}` and `{status}` (`success` or
+/// `failed`) for transactions, `{pubkey}` and `{owner}` for account updates, `{commitment}` for
+/// slot updates.
+/// A transaction is published once for every program it invokes when its template uses `{program_id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigRoutingKeys {
+    #[serde(default = "ConfigRoutingKeys::default_transaction")]
+    pub transaction: String,
+    #[serde(default = "ConfigRoutingKeys::default_account")]
+    pub account: String,
+    #[serde(default = "ConfigRoutingKeys::default_block_meta")]
+    pub block_meta: String,
+    #[serde(default = "ConfigRoutingKeys::default_slot")]
+    pub slot: String,
+    /// Blocks are only published when the block builder is enabled.
+    #[serde(default = "ConfigRoutingKeys::default_block")]
+    pub block: String,
+    /// Entries are only published with `entry_notifications`.
+    #[serde(default = "ConfigRoutingKeys::default_entry")]
+    pub entry: String,
+}
+
+impl ConfigRoutingKeys {
+    pub fn default_transaction() -> String {
+        "transactionsDurable".to_string()
+    }
+    pub fn default_account() -> String {
+        "accountChangesDurable".to_string()
+    }
+    pub fn default_block_meta() -> String {
+        "blockMetaDurable".to_string()
+    }
+    pub fn default_slot() -> String {
+        "slotsDurable".to_string()
+    }
+    pub fn default_block() -> String {
+        "blocksDurable".to_string()
+    }
+    pub fn default_entry() -> String {
+        "entriesDurable".to_string()
+    }
+}
+
+impl Default for ConfigRoutingKeys {
+    fn default() -> Self {
+        Self {
+            transaction: Self::default_transaction(),
+            account: Self::default_account(),
+            block_meta: Self::default_block_meta(),
+            slot: Self::default_slot(),
+            block: Self::default_block(),
+            entry: Self::default_entry(),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::Context;
+use arc_swap::{ArcSwap, Guard};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use quic_geyser_common::{
+    channel_message::ChannelMessage,
+    filters::{
+        AccountFilter, AccountFilterType, MemcmpFilter, MemcmpFilterData, TransactionAccounts,
+        TransactionFilter,
+    },
+};
+use solana_sdk::{bs58, pubkey::Pubkey};
+
+use crate::config::{
+    Config, ConfigAccountDataFilter, ConfigAccountFilter, ConfigFilterScope, ConfigMemcmp,
+    ConfigTransactionFilter, ConfigTransactionPolicies, ConfigTransactionPolicy, MemcmpEncoding,
+};
+
+/// Filters of every output of the plugin, an output without filters gets every event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PluginFilters {
+    pub quic: Option<FilterScope>,
+    pub mq: Option<FilterScope>,
+    pub block_builder: Option<FilterScope>,
+    pub transaction_policies: ConfigTransactionPolicies,
+}
+
+/// Filters read by the geyser callbacks and replaced as a whole on reload, so a callback sees
+/// either the old or the new filters, never a mix of both. Loading them takes no lock.
+#[derive(Debug, Clone, Default)]
+pub struct SharedFilters {
+    filters: Arc<ArcSwap<PluginFilters>>,
+}
+
+impl SharedFilters {
+    pub fn new(filters: PluginFilters) -> Self {
+        Self {
+            filters: Arc::new(ArcSwap::from_pointee(filters)),
+        }
+    }
+
+    pub fn load(&self) -> Guard<Arc<PluginFilters>> {
+        self.filters.load()
+    }
+
+    /// Replaces the filters, returns the previous ones.
+    pub fn store(&self, filters: PluginFilters) -> Arc<PluginFilters> {
+        self.filters.swap(Arc::new(filters))
+    }
+}
+
+/// Outputs an event is sent to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outputs {
+    pub quic: bool,
+    pub mq: bool,
+    pub block_builder: bool,
+}
+
+impl Outputs {
+    pub fn any(&self) -> bool {
+        self.quic || self.mq || self.block_builder
+    }
+}
+
+impl PluginFilters {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            quic: config
+                .quic_filters
+                .as_ref()
+                .map(FilterScope::new)
+                .transpose()
+                .context("quic_filters")?,
+            mq: Some(FilterScope::new(&config.mq_filters())?),
+            block_builder: config
+                .block_builder_filters
+                .as_ref()
+                .map(FilterScope::new)
+                .transpose()
+                .context("block_builder_filters")?,
+            transaction_policies: config.transaction_policies,
+        })
+    }
+
+    pub fn account_outputs(&self, message: &ChannelMessage) -> Outputs {
+        self.outputs(|scope| scope.allows_account(message))
+    }
+
+    /// The failed and vote policy of an output is checked before its filters.
+    pub fn transaction_outputs(
+        &self,
+        accounts: &TransactionAccounts,
+        is_vote: bool,
+        failed: bool,
+    ) -> Outputs {
+        let allowed = |policy: &ConfigTransactionPolicy, scope: &Option<FilterScope>| {
+            policy.allows(is_vote, failed)
+                && scope.as_ref().map_or(true, |scope| {
+                    scope.allows_transaction(accounts, is_vote, failed)
+                })
+        };
+        let policies = &self.transaction_policies;
+        Outputs {
+            quic: allowed(&policies.quic, &self.quic),
+            mq: allowed(&policies.mq, &self.mq),
+            block_builder: allowed(&policies.block_builder, &self.block_builder),
+        }
+    }
+
+    fn outputs(&self, allows: impl Fn(&FilterScope) -> bool) -> Outputs {
+        let allowed = |scope: &Option<FilterScope>| scope.as_ref().map_or(true, &allows);
+        Outputs {
+            quic: allowed(&self.quic),
+            mq: allowed(&self.mq),
+            block_builder: allowed(&self.block_builder),
+        }
+    }
+}
+
+/// Allowlists deciding which account updates and transactions an output gets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterScope {
+    // owners of `account_update_pubkeys`
+    account_owners: Vec<Pubkey>,
+    account_filters: Vec<AccountFilter>,
+    transaction_pubkeys: Vec<Pubkey>,
+    transaction_filters: Vec<TransactionFilter>,
+}
+
+impl FilterScope {
+    pub fn new(config: &ConfigFilterScope) -> anyhow::Result<Self> {
+        Ok(Self {
+            account_owners: parse_pubkeys(&config.account_update_pubkeys)
+                .context("account_update_pubkeys")?,
+            account_filters: config
+                .account_filters
+                .iter()
+                .enumerate()
+                .map(|(index, filter)| {
+                    account_filter(filter).with_context(|| format!("account_filters[{index}]"))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            transaction_pubkeys: parse_pubkeys(&config.transaction_pubkeys)
+                .context("transaction_pubkeys")?,
+            transaction_filters: config
+                .transaction_filters
+                .iter()
+                .enumerate()
+                .map(|(index, filter)| {
+                    transaction_filter(filter)
+                        .with_context(|| format!("transaction_filters[{index}]"))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    /// An account update is allowed if its owner is in `account_update_pubkeys`
+    /// or if it matches one of `account_filters`.
+    pub fn allows_account(&self, message: &ChannelMessage) -> bool {
+        let ChannelMessage::Account(account_data, _, _) = message else {
+            return false;
+        };
+        self.account_owners.contains(&account_data.account.owner)
+            || self
+                .account_filters
+                .iter()
+                .any(|filter| filter.allows(message))
+    }
+
+    /// A transaction is allowed if it uses one of `transaction_pubkeys`
+    /// or if it matches one of `transaction_filters`.
+    pub fn allows_transaction(
+        &self,
+        accounts: &TransactionAccounts,
+        is_vote: bool,
+        failed: bool,
+    ) -> bool {
+        accounts
+            .iter()
+            .any(|(account, _, _)| self.transaction_pubkeys.contains(account))
+            || self
+                .transaction_filters
+                .iter()
+                .any(|filter| filter.allows_transaction(accounts, is_vote, failed))
+    }
+}
+
+fn parse_pubkeys(pubkeys: &[String]) -> anyhow::Result<Vec<Pubkey>> {
+    pubkeys.iter().map(|pubkey| parse_pubkey(pubkey)).collect()
+}
+
+fn parse_pubkey(pubkey: &str) -> anyhow::Result<Pubkey> {
+    Pubkey::from_str(pubkey).with_context(|| format!("invalid pubkey {pubkey}"))
+}
+
+fn account_filter(filter: &ConfigAccountFilter) -> anyhow::Result<AccountFilter> {
+    if filter.owner.is_none() && filter.accounts.is_empty() {
+        anyhow::bail!("an account filter needs an owner or accounts");
+    }
+    if filter.owner.is_none() && !filter.filters.is_empty() {
+        anyhow::bail!("data filters only apply to the accounts of an owner");
+    }
+    Ok(AccountFilter {
+        owner: filter.owner.as_deref().map(parse_pubkey).transpose()?,
+        accounts: (!filter.accounts.is_empty())
+            .then(|| {
+                filter
+                    .accounts
+                    .iter()
+                    .map(|pubkey| parse_pubkey(pubkey))
+                    .collect()
+            })
+            .transpose()?,
+        filters: (!filter.filters.is_empty())
+            .then(|| filter.filters.iter().map(account_data_filter).collect())
+            .transpose()?,
+    })
+}
+
+fn transaction_filter(filter: &ConfigTransactionFilter) -> anyhow::Result<TransactionFilter> {
+    Ok(TransactionFilter {
+        account_include: parse_pubkeys(&filter.account_include).context("account_include")?,
+        account_required: parse_pubkeys(&filter.account_required).context("account_required")?,
+        account_exclude: parse_pubkeys(&filter.account_exclude).context("account_exclude")?,
+        signer_only: filter.signer_only,
+        writable_only: filter.writable_only,
+        vote: filter.vote,
+        failed: filter.failed,
+    })
+}
+
+fn account_data_filter(filter: &ConfigAccountDataFilter) -> anyhow::Result<AccountFilterType> {
+    Ok(match filter {
+        ConfigAccountDataFilter::Datasize(size) => AccountFilterType::Datasize(*size),
+        ConfigAccountDataFilter::Memcmp(ConfigMemcmp {
+            offset,
+            bytes,
+            encoding,
+        }) => {
+            let bytes = match encoding {
+                MemcmpEncoding::Base58 => bs58::decode(bytes).into_vec()?,
+                MemcmpEncoding::Base64 => BASE64.decode(bytes)?,
+            };
+            AccountFilterType::Memcmp(MemcmpFilter {
+                offset: *offset,
+                data: MemcmpFilterData::Bytes(bytes),
+            })
+        }
+    })
+}
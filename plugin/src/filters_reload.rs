@@ -0,0 +1,217 @@
+//! Reload of the filters and transaction policies when the config file changes, without
+//! restarting the plugin (`Config::filters_reload`).
+//!
+//! The config file is polled rather than watched with inotify or reloaded on SIGHUP: inotify
+//! watches are lost when the file is replaced by a rename or a symlink swap, and a signal handler
+//! installed by the plugin would change the signal handling of the whole validator and stay
+//! installed once the plugin library is unloaded. `agave-validator plugin reload` goes through
+//! `on_load` with `is_reload`, see `reloaded_filters`.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{
+    config::{Config, ConfigFilterScope, ConfigFiltersReload, ConfigTransactionPolicies},
+    filters::{PluginFilters, SharedFilters},
+};
+
+/// Loads the filters of the config file into the `SharedFilters` of the plugin.
+/// Other settings of the config file are only read when the plugin loads.
+pub struct FiltersReloader {
+    config_file: PathBuf,
+    // config the active filters were built from
+    config: Config,
+    filters: SharedFilters,
+    file_version: Option<(SystemTime, u64)>,
+}
+
+impl FiltersReloader {
+    pub fn new(config_file: PathBuf, config: Config, filters: SharedFilters) -> Self {
+        Self {
+            file_version: file_version(&config_file),
+            config_file,
+            config,
+            filters,
+        }
+    }
+
+    /// Reloads the filters if the config file was modified since the last check.
+    pub fn check(&mut self) -> anyhow::Result<Vec<String>> {
+        let file_version = file_version(&self.config_file);
+        if file_version == self.file_version {
+            return Ok(vec![]);
+        }
+        self.file_version = file_version;
+        self.reload()
+    }
+
+    /// Activates the filters of the config file once they are all valid, the current ones stay
+    /// active otherwise. Returns the changes applied, see `filters_diff`.
+    pub fn reload(&mut self) -> anyhow::Result<Vec<String>> {
+        let config = Config::load_from_file(&self.config_file)
+            .with_context(|| format!("loading {}", self.config_file.display()))?;
+        let filters = PluginFilters::new(&config)?;
+        let changes = filters_diff(&self.config, &config);
+        if !changes.is_empty() {
+            self.filters.store(filters);
+        }
+        self.config = config;
+        Ok(changes)
+    }
+
+    /// Checks the config file every `poll_interval_ms` until `stop` is closed.
+    pub fn start(mut self, reload: ConfigFiltersReload, stop: Receiver<()>) -> JoinHandle<()> {
+        let poll_interval = Duration::from_millis(reload.poll_interval_ms);
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(poll_interval) {
+                match self.check() {
+                    Ok(changes) if changes.is_empty() => {}
+                    Ok(changes) => log::info!(
+                        "Reloaded filters from {}: {}",
+                        self.config_file.display(),
+                        changes.join("; ")
+                    ),
+                    Err(e) => log::error!("Keeping the current filters: {e:#}"),
+                }
+            }
+        })
+    }
+}
+
+// modification time and size, a missing file has no version
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Filters and transaction policies of a plugin reloaded by the validator, as the changes from a
+/// plugin without filters: the validator unloads the previous instance before loading the new one.
+pub fn reloaded_filters(config: &Config) -> Vec<String> {
+    let unfiltered = Config {
+        account_update_pubkeys: vec![],
+        transaction_pubkeys: vec![],
+        account_filters: vec![],
+        transaction_filters: vec![],
+        quic_filters: None,
+        block_builder_filters: None,
+        transaction_policies: ConfigTransactionPolicies::default(),
+        ..config.clone()
+    };
+    filters_diff(&unfiltered, config)
+}
+
+/// Changes of the filters and transaction policies between two configs, one line per changed
+/// list or policy with the entries added and removed.
+pub fn filters_diff(old: &Config, new: &Config) -> Vec<String> {
+    let mut changes = vec![];
+    scope_diff(
+        "mq",
+        Some(&old.mq_filters()),
+        Some(&new.mq_filters()),
+        &mut changes,
+    );
+    scope_diff(
+        "quic_filters",
+        old.quic_filters.as_ref(),
+        new.quic_filters.as_ref(),
+        &mut changes,
+    );
+    scope_diff(
+        "block_builder_filters",
+        old.block_builder_filters.as_ref(),
+        new.block_builder_filters.as_ref(),
+        &mut changes,
+    );
+
+    let (old_policies, new_policies) = (&old.transaction_policies, &new.transaction_policies);
+    for (output, old_policy, new_policy) in [
+        ("quic", old_policies.quic, new_policies.quic),
+        ("mq", old_policies.mq, new_policies.mq),
+        (
+            "block_builder",
+            old_policies.block_builder,
+            new_policies.block_builder,
+        ),
+    ] {
+        if old_policy != new_policy {
+            changes.push(format!(
+                "transaction_policies.{output}: {old_policy:?} -> {new_policy:?}"
+            ));
+        }
+    }
+    changes
+}
+
+fn scope_diff(
+    name: &str,
+    old: Option<&ConfigFilterScope>,
+    new: Option<&ConfigFilterScope>,
+    changes: &mut Vec<String>,
+) {
+    match (old, new) {
+        (Some(_), None) => changes.push(format!("{name} removed, the output gets every event")),
+        (None, Some(_)) => changes.push(format!("{name} set")),
+        _ => {}
+    }
+    let unset = ConfigFilterScope::default();
+    let (old, new) = (old.unwrap_or(&unset), new.unwrap_or(&unset));
+    list_diff(
+        name,
+        "account_update_pubkeys",
+        &old.account_update_pubkeys,
+        &new.account_update_pubkeys,
+        changes,
+    );
+    list_diff(
+        name,
+        "transaction_pubkeys",
+        &old.transaction_pubkeys,
+        &new.transaction_pubkeys,
+        changes,
+    );
+    list_diff(
+        name,
+        "account_filters",
+        &old.account_filters,
+        &new.account_filters,
+        changes,
+    );
+    list_diff(
+        name,
+        "transaction_filters",
+        &old.transaction_filters,
+        &new.transaction_filters,
+        changes,
+    );
+}
+
+fn list_diff<T: PartialEq + Serialize>(
+    scope: &str,
+    name: &str,
+    old: &[T],
+    new: &[T],
+    changes: &mut Vec<String>,
+) {
+    // entries of `from` missing from `other`, as JSON
+    let missing = |from: &[T], other: &[T]| {
+        from.iter()
+            .filter(|entry| !other.contains(entry))
+            .map(|entry| serde_json::to_string(entry).unwrap_or_default())
+            .collect::<Vec<_>>()
+    };
+    let (added, removed) = (missing(new, old), missing(old, new));
+    if !added.is_empty() || !removed.is_empty() {
+        changes.push(format!(
+            "{scope}.{name}: added [{}], removed [{}]",
+            added.join(", "),
+            removed.join(", ")
+        ));
+    }
+}
@@ -1,147 +1,851 @@
-use std::{sync::mpsc::Receiver, time::Duration};
 use anyhow::Result;
-use lapin::{
-    options::{BasicPublishOptions, QueueDeclareOptions},
-    types::FieldTable,
-    BasicProperties, Connection, ConnectionProperties,
+use lapin::publisher_confirm::Confirmation;
+use prometheus::{
+    exponential_buckets, histogram_opts, opts, register_histogram, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Histogram, IntCounter, IntCounterVec, IntGauge,
 };
-use quic_geyser_common::channel_message::ChannelMessage;
+use quic_geyser_common::{channel_message::ChannelMessage, types::block_meta::SlotMeta};
+use solana_sdk::commitment_config::CommitmentLevel;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::mpsc::{RecvTimeoutError, TryRecvError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{task::JoinSet, time::error::Elapsed};
+
+use crate::{
+    account_coalescer::AccountCoalescer,
+    brokers::Brokers,
+    commitment_buffer::CommitmentBuffer,
+    config::{
+        ConfigCompression, ConfigMq, ConfigResend, ConfigRoutingKeys, ConfigTopology,
+        PayloadEncoding,
+    },
+    mq_channel::MqReceiver,
+    mq_publisher::{AmqpConnector, Connector, Publisher},
+    payload::{
+        compress, encode_account, encode_block, encode_block_meta, encode_entry, encode_slot,
+        encode_transaction, TransactionErrorCategory, COMMITMENT_HEADER, ERROR_HEADER,
+        MESSAGE_TYPE_HEADER, ORIGINAL_EXCHANGE_HEADER, ORIGINAL_ROUTING_KEY_HEADER, OWNER_HEADER,
+        PROGRAM_IDS_HEADER, SLOT_HEADER, TEXT_CONTENT_TYPE,
+    },
+    routing::{
+        account_routing_key, block_meta_routing_key, block_routing_key, entry_routing_key,
+        routed_queues, slot_routing_key, transaction_routing_keys,
+    },
+    spool::{HeaderValue, MessageProperties, Spool, SpoolEntry, SpoolPosition},
+};
+
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+// how long we wait for confirms before looking at the plugin channel again
+const CONFIRM_WAIT: Duration = Duration::from_millis(10);
+// messages moved from the plugin channel to the spool before publishing again
+const MAX_SPOOL_BATCH: usize = 1024;
+
+lazy_static::lazy_static! {
+    static ref MQ_MESSAGES_PUBLISHED: IntCounterVec =
+       register_int_counter_vec!(opts!("quic_plugin_mq_messages_published", "Number of messages confirmed by the broker per queue"), &["queue"]).unwrap();
+
+    static ref MQ_MESSAGES_UNROUTED: IntCounter =
+       register_int_counter!(opts!("quic_plugin_mq_messages_unrouted", "Number of messages confirmed by the broker matching none of the configured queues")).unwrap();
+
+    static ref MQ_NACKS: IntCounter =
+       register_int_counter!(opts!("quic_plugin_mq_nacks", "Number of messages nacked by the broker")).unwrap();
+
+    static ref MQ_CONFIRM_TIMEOUTS: IntCounter =
+       register_int_counter!(opts!("quic_plugin_mq_confirm_timeouts", "Number of messages not confirmed in time by the broker")).unwrap();
+
+    static ref MQ_SERIALIZATION_FAILURES: IntCounter =
+       register_int_counter!(opts!("quic_plugin_mq_serialization_failures", "Number of messages which could not be serialized")).unwrap();
 
-use tokio::time::sleep;
+    static ref MQ_CONFIRM_LATENCY: Histogram =
+       register_histogram!(histogram_opts!("quic_plugin_mq_confirm_latency_seconds", "Time between publishing a message and its broker confirm", exponential_buckets(0.001, 2.0, 15).unwrap())).unwrap();
+
+    static ref MQ_SPOOL_SIZE: IntGauge =
+       register_int_gauge!(opts!("quic_plugin_mq_spool_size", "Bytes of unconfirmed messages in the MQ spool")).unwrap();
+}
+
+/// Publishes plugin messages to AMQP.
+/// Every message is first written to the spool and only removed from it once the broker
+/// confirmed it, so broker outages and plugin restarts replay unconfirmed messages in order.
+/// Up to `max_in_flight` messages are published without waiting for their confirms,
+/// nacked or timed out messages are published again after a backoff, without holding back
+/// the messages behind them.
+/// If the connection or publish fails, we log it, keep spooling, and try the next broker
+/// after a backoff delay.
+/// The spool is opened by the caller, so a spool which cannot be opened fails the plugin load.
+pub async fn run_lavin_mq_loop(
+    amqp_urls: Vec<String>,
+    mq_rx: MqReceiver,
+    spool: Spool,
+    mq_config: ConfigMq,
+) -> Result<()> {
+    run_mq_loop(AmqpConnector, amqp_urls, mq_rx, spool, mq_config).await
+}
+
+/// `run_lavin_mq_loop` with the brokers reached through `connector`.
+pub async fn run_mq_loop<C: Connector>(
+    mut connector: C,
+    amqp_urls: Vec<String>,
+    mq_rx: MqReceiver,
+    spool: Spool,
+    mq_config: ConfigMq,
+) -> Result<()> {
+    let mut brokers = Brokers::new(amqp_urls, &mq_config.reconnect)?;
+    let mut spooler = Spooler {
+        mq_rx,
+        spool,
+        routing_keys: mq_config.topology.routing_keys.clone(),
+        encoding: mq_config.encoding,
+        compression: mq_config.compression.clone(),
+        account_coalescer: mq_config
+            .coalesce_accounts
+            .as_ref()
+            .map(AccountCoalescer::new),
+        commitment_buffer: CommitmentBuffer::new(mq_config.commitment),
+        dead_letter: DeadLetter::new(&mq_config.topology),
+        mq_rx_closed: false,
+        shutdown_timeout: Duration::from_millis(mq_config.shutdown_timeout_ms),
+        shutdown_deadline: None,
+    };
+    let max_in_flight = mq_config.max_in_flight.max(1);
+    let confirm_timeout = Duration::from_millis(mq_config.confirm_timeout_ms);
 
-/// Example of a run_lavin_mq_loop with reconnection logic.
-/// If the connection or publish fails, we log it, sleep, and try again.
-pub async fn run_lavin_mq_loop(amqp_url: &str, mq_rx: Receiver<ChannelMessage>) -> Result<()> {
     'outer: loop {
-        // 1) Connect to AMQP
-        let conn = match Connection::connect(amqp_url, ConnectionProperties::default()).await {
-            Ok(c) => c,
+        if spooler.shutdown_expired() {
+            break 'outer;
+        }
+        // 1) Connect to AMQP and declare the topology
+        let mut publisher = match connector
+            .connect(brokers.current(), &mq_config.topology)
+            .await
+        {
+            Ok(publisher) => publisher,
             Err(e) => {
-                log::error!("Error connecting to AMQP: {e}, retrying in 5s...");
-                sleep(Duration::from_secs(5)).await;
+                let broker = brokers.current_name();
+                let delay = brokers.failed();
+                log::error!(
+                    "Error setting up AMQP on {broker}: {e:#}, trying {} in {delay:?}",
+                    brokers.current_name()
+                );
+                if spooler.mq_rx_closed {
+                    break 'outer;
+                }
+                spooler.receive_for(delay).await;
                 continue 'outer;
             }
         };
+        brokers.connected();
 
-        // 2) Create channel
-        let channel = match conn.create_channel().await {
-            Ok(ch) => ch,
-            Err(e) => {
-                log::error!("Error creating channel: {e}, retrying in 5s...");
-                sleep(Duration::from_secs(5)).await;
+        // 2) Replay everything the broker has not confirmed yet, then process new messages
+        spooler.spool.rewind();
+        let mut window =
+            InFlightWindow::new(confirm_timeout, &mq_config.resend, &mq_config.topology);
+        loop {
+            // only wait on the plugin channel when there is nothing left to publish
+            let idle = window.is_empty() && !spooler.spool.has_unread();
+            spooler.receive(idle.then_some(IDLE_WAIT)).await;
+
+            if let Err(e) = window.resend_due(&mut publisher).await {
+                log::error!("AMQP publish error on {}: {e}", brokers.current_name());
+                spooler.receive_for(brokers.failed()).await;
                 continue 'outer;
             }
-        };
-        let queue_options = QueueDeclareOptions {
-            durable: true,       // This makes the queue durable
-            ..Default::default()
-        };
+            while window.len() < max_in_flight {
+                let Some((position, entry)) = spooler.spool.next()? else {
+                    break;
+                };
+                if let Err(e) = window.publish(&mut publisher, position, entry).await {
+                    log::error!("AMQP publish error on {}: {e}", brokers.current_name());
+                    spooler.receive_for(brokers.failed()).await;
+                    continue 'outer;
+                }
+            }
+
+            if window.is_empty() {
+                if spooler.mq_rx_closed && !spooler.spool.has_unread() {
+                    break;
+                }
+                continue;
+            }
+            if spooler.shutdown_expired() {
+                log::warn!(
+                    "MQ shutdown timeout reached with {} messages waiting for their confirm",
+                    window.len()
+                );
+                break;
+            }
 
-        // 3) Declare both queues
-       for queue_name in ["transactionsDurable", "accountChangesDurable", "blockMetaDurable"] {
-            if let Err(e) = channel
-                .queue_declare(queue_name, queue_options.clone(), FieldTable::default())
+            if let Err(e) = window
+                .process_confirms(CONFIRM_WAIT, spooler.dead_letter.as_ref())
                 .await
             {
-                eprintln!("Error declaring queue {}: {}", queue_name, e);
+                log::error!("AMQP confirm error on {}: {e}", brokers.current_name());
+                spooler.receive_for(brokers.failed()).await;
+                continue 'outer;
             }
+            window.release(&mut spooler.spool)?;
+            MQ_SPOOL_SIZE.set(spooler.spool.size() as i64);
         }
 
-        log::info!("Connected to AMQP and declared queues successfully.");
+        // 3) The plugin is unloading
+        if let Err(e) = publisher.close().await {
+            log::warn!(
+                "Error closing AMQP connection to {}: {e:#}",
+                brokers.current_name()
+            );
+        }
+        break 'outer;
+    }
 
-        // 4) Process messages
-        while let Ok(msg) = mq_rx.recv() {
-            match msg {
-                ChannelMessage::Transaction(tx) => {
-                    let payload = match serde_json::to_vec(&tx) {
-                        Ok(p) => p,
-                        Err(serde_err) => {
-                            log::error!("Failed to serialize transaction: {serde_err}");
-                            continue;
-                        }
-                    };
+    if spooler.spool.has_unread() {
+        log::warn!(
+            "mq_rx closed, leaving {} bytes of unconfirmed messages in the MQ spool",
+            spooler.spool.size()
+        );
+    } else {
+        log::warn!("mq_rx closed, shutting down lavin MQ loop");
+    }
+    spooler.spool.flush()?;
+    Ok(())
+}
 
-                    if let Err(e) = publish_message(&channel, "transactionsDurable", &payload).await {
-                        log::error!("AMQP publish error for transaction: {e}");
-                        sleep(Duration::from_secs(5)).await;
-                        continue 'outer;
-                    }
+type ConfirmResult = std::result::Result<lapin::Result<Confirmation>, Elapsed>;
+
+struct InFlight {
+    entry: SpoolEntry,
+    confirmed: bool,
+    retry: Retry,
+}
+
+/// Failed deliveries of a message.
+#[derive(Debug, Clone, Copy, Default)]
+struct Retry {
+    nacks: u32,
+    // nacks and confirm timeouts, the resend backoff grows with them
+    failures: u32,
+    // set while the message waits to be published again
+    resend_at: Option<Instant>,
+}
+
+/// Published messages waiting for their broker confirm.
+///
+/// A message waiting for its resend while later messages are confirmed is moved to the end of
+/// the spool, so the spool is acked past it instead of holding them back.
+struct InFlightWindow {
+    // ordered like the spool, so the spool is only acked up to the first unconfirmed message
+    entries: BTreeMap<SpoolPosition, InFlight>,
+    // delivery tag -> position of the message in the spool and when it was published
+    deliveries: HashMap<u64, (SpoolPosition, Instant)>,
+    confirms: JoinSet<(u64, ConfirmResult)>,
+    confirm_timeout: Duration,
+    resend: ConfigResend,
+    // failed deliveries of the messages moved to the end of the spool, by their new position
+    requeued: BTreeMap<SpoolPosition, Retry>,
+    // to count confirmed messages per queue
+    topology: ConfigTopology,
+}
+
+impl InFlightWindow {
+    fn new(confirm_timeout: Duration, resend: &ConfigResend, topology: &ConfigTopology) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            deliveries: HashMap::new(),
+            confirms: JoinSet::new(),
+            confirm_timeout,
+            resend: resend.clone(),
+            requeued: BTreeMap::new(),
+            topology: topology.clone(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    async fn publish<P: Publisher>(
+        &mut self,
+        publisher: &mut P,
+        position: SpoolPosition,
+        entry: SpoolEntry,
+    ) -> Result<()> {
+        let retry = self.requeued.remove(&position).unwrap_or_default();
+        // a requeued message keeps waiting for its resend
+        if retry.resend_at.is_none() {
+            self.send(publisher, position, &entry).await?;
+        }
+        self.entries.insert(
+            position,
+            InFlight {
+                entry,
+                confirmed: false,
+                retry,
+            },
+        );
+        Ok(())
+    }
+
+    async fn send<P: Publisher>(
+        &mut self,
+        publisher: &mut P,
+        position: SpoolPosition,
+        entry: &SpoolEntry,
+    ) -> Result<()> {
+        let (delivery_tag, confirm) = publisher.publish(entry).await?;
+        let confirm_timeout = self.confirm_timeout;
+        self.confirms.spawn(async move {
+            (
+                delivery_tag,
+                tokio::time::timeout(confirm_timeout, confirm).await,
+            )
+        });
+        self.deliveries
+            .insert(delivery_tag, (position, Instant::now()));
+        Ok(())
+    }
+
+    /// Waits up to `wait` for the next confirm, then handles every confirm already received.
+    async fn process_confirms(
+        &mut self,
+        wait: Duration,
+        dead_letter: Option<&DeadLetter>,
+    ) -> Result<()> {
+        if self.confirms.is_empty() {
+            // only messages waiting for their resend
+            tokio::time::sleep(wait).await;
+            return Ok(());
+        }
+        let Ok(Some(first)) = tokio::time::timeout(wait, self.confirms.join_next()).await else {
+            return Ok(());
+        };
+        let mut joined = Some(first);
+        while let Some(result) = joined {
+            let (delivery_tag, confirm) = result?;
+            self.on_confirm(delivery_tag, confirm, dead_letter)?;
+            joined = self.confirms.try_join_next();
+        }
+        Ok(())
+    }
+
+    fn on_confirm(
+        &mut self,
+        delivery_tag: u64,
+        confirm: ConfirmResult,
+        dead_letter: Option<&DeadLetter>,
+    ) -> Result<()> {
+        let Some((position, published_at)) = self.deliveries.remove(&delivery_tag) else {
+            return Ok(());
+        };
+        if let Ok(Ok(_)) = &confirm {
+            MQ_CONFIRM_LATENCY.observe(published_at.elapsed().as_secs_f64());
+        }
+        match confirm {
+            Ok(Ok(confirmation)) if !confirmation.is_nack() => {
+                if let Some(in_flight) = self.entries.get_mut(&position) {
+                    in_flight.confirmed = true;
+                    count_published(&self.topology, &in_flight.entry);
                 }
-                ChannelMessage::Account(account_data, slot, is_startup) => {
-                    // Create a structure to serialize account data with metadata
-                    let account_message = serde_json::json!({
-                        "account": {
-                            "pubkey": account_data.pubkey.to_string(),
-                            "lamports": account_data.account.lamports,
-                            "owner": account_data.account.owner.to_string(),
-                            "executable": account_data.account.executable,
-                            "rentEpoch": account_data.account.rent_epoch,
-                            "data": account_data.account.data,
-                        },
-                        "slot": slot,
-                        "isStartup": is_startup,
-                        "writeVersion": account_data.write_version,
-                    });
-
-                    let payload = match serde_json::to_vec(&account_message) {
-                        Ok(p) => p,
-                        Err(serde_err) => {
-                            log::error!("Failed to serialize account data: {serde_err}");
-                            continue;
+                Ok(())
+            }
+            Ok(Ok(_)) => {
+                MQ_NACKS.inc();
+                if let Some(in_flight) = self.entries.get_mut(&position) {
+                    in_flight.retry.nacks += 1;
+                    match dead_letter {
+                        Some(dead_letter)
+                            if !in_flight.entry.dead_letter
+                                && in_flight.retry.nacks >= dead_letter.max_nacks =>
+                        {
+                            log::error!(
+                                "Broker nacked delivery {delivery_tag} for {} {} times, dead-lettering it",
+                                in_flight.entry.routing_key,
+                                in_flight.retry.nacks
+                            );
+                            in_flight.entry = dead_letter.entry(
+                                &in_flight.entry,
+                                format!("nacked by the broker {} times", in_flight.retry.nacks),
+                            );
+                            in_flight.retry.nacks = 0;
+                        }
+                        _ => {
+                            log::warn!("Broker nacked delivery {delivery_tag}, publishing it again")
                         }
-                    };
-
-                    if let Err(e) = publish_message(&channel, "accountChangesDurable", &payload).await {
-                        log::error!("AMQP publish error for account change: {e}");
-                        sleep(Duration::from_secs(5)).await;
-                        continue 'outer;
                     }
                 }
+                self.schedule_resend(position);
+                Ok(())
+            }
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => {
+                MQ_CONFIRM_TIMEOUTS.inc();
+                log::warn!(
+                    "Delivery {delivery_tag} was not confirmed after {:?}, publishing it again",
+                    self.confirm_timeout
+                );
+                self.schedule_resend(position);
+                Ok(())
+            }
+        }
+    }
 
-                ChannelMessage::BlockMeta(block_meta) => {
-                    let payload = match serde_json::to_vec(&block_meta) {
-                        Ok(p) => p,
-                        Err(serde_err) => {
-                            log::error!("Failed to serialize block metadata: {serde_err}");
-                            continue;
-                        }
-                    };
+    fn schedule_resend(&mut self, position: SpoolPosition) {
+        let Some(in_flight) = self.entries.get_mut(&position) else {
+            return;
+        };
+        let retry = &mut in_flight.retry;
+        retry.failures += 1;
+        let delay_ms = self
+            .resend
+            .initial_delay_ms
+            .saturating_mul(1 << (retry.failures - 1).min(32))
+            .min(self.resend.max_delay_ms);
+        retry.resend_at = Some(Instant::now() + Duration::from_millis(delay_ms));
+    }
 
-                    if let Err(e) = publish_message(&channel, "blockMetaDurable", &payload).await {
-                        log::error!("AMQP publish error for block metadata: {e}");
-                        sleep(Duration::from_secs(5)).await;
-                        continue 'outer;
-                    }
+    /// Publishes again the messages whose resend delay is over.
+    async fn resend_due<P: Publisher>(&mut self, publisher: &mut P) -> Result<()> {
+        let now = Instant::now();
+        let due = self
+            .entries
+            .iter()
+            .filter(|(_, in_flight)| {
+                in_flight
+                    .retry
+                    .resend_at
+                    .is_some_and(|resend_at| resend_at <= now)
+            })
+            .map(|(position, _)| *position)
+            .collect::<Vec<_>>();
+        for position in due {
+            let in_flight = self.entries.get_mut(&position).expect("due message");
+            in_flight.retry.resend_at = None;
+            let entry = in_flight.entry.clone();
+            self.send(publisher, position, &entry).await?;
+        }
+        Ok(())
+    }
+
+    /// Acks the spool up to the first message which is not confirmed yet.
+    ///
+    /// When that message waits for its resend and later ones are confirmed, it is appended
+    /// again at the end of the spool, so the confirmed ones are acked and leave the window.
+    fn release(&mut self, spool: &mut Spool) -> Result<()> {
+        while let Some((&position, oldest)) = self.entries.first_key_value() {
+            if oldest.confirmed {
+                self.entries.remove(&position);
+                spool.ack(position)?;
+                continue;
+            }
+            if oldest.retry.resend_at.is_none()
+                || !self.entries.values().any(|in_flight| in_flight.confirmed)
+            {
+                break;
+            }
+            // with a full spool the message stays where it is, new messages are dropped anyway
+            let Some(requeued_position) = spool.requeue(&oldest.entry)? else {
+                break;
+            };
+            let in_flight = self.entries.remove(&position).expect("oldest message");
+            self.requeued.insert(requeued_position, in_flight.retry);
+            spool.ack(position)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts a confirmed message under every queue the broker routed it to.
+fn count_published(topology: &ConfigTopology, entry: &SpoolEntry) {
+    let queues = match (&topology.dead_letter, entry.dead_letter) {
+        (Some(dead_letter), true) => routed_queues(
+            &dead_letter.exchange,
+            dead_letter.exchange_type,
+            &dead_letter.queues,
+            &entry.routing_key,
+        ),
+        _ => routed_queues(
+            &topology.exchange,
+            topology.exchange_type,
+            &topology.queues,
+            &entry.routing_key,
+        ),
+    };
+    if queues.is_empty() {
+        MQ_MESSAGES_UNROUTED.inc();
+    }
+    for queue in queues {
+        MQ_MESSAGES_PUBLISHED.with_label_values(&[queue]).inc();
+    }
+}
+
+/// Moves messages from the plugin channel to the spool.
+struct Spooler {
+    mq_rx: MqReceiver,
+    spool: Spool,
+    routing_keys: ConfigRoutingKeys,
+    encoding: PayloadEncoding,
+    compression: Option<ConfigCompression>,
+    account_coalescer: Option<AccountCoalescer>,
+    commitment_buffer: CommitmentBuffer,
+    dead_letter: Option<DeadLetter>,
+    mq_rx_closed: bool,
+    shutdown_timeout: Duration,
+    // set once the plugin closed the channel
+    shutdown_deadline: Option<Instant>,
+}
+
+impl Spooler {
+    /// Spools incoming messages, waiting up to `wait` for the first one, then syncs the spool if
+    /// it is due.
+    async fn receive(&mut self, wait: Option<Duration>) {
+        self.receive_batch(wait).await;
+        if let Err(e) = self.spool.sync_if_due() {
+            log::error!("Failed to sync MQ spool: {e}");
+        }
+    }
+
+    async fn receive_batch(&mut self, wait: Option<Duration>) {
+        if self.mq_rx_closed {
+            return;
+        }
+        if let Some(wait) = wait {
+            // waits without blocking the runtime, which also drives the broker confirms
+            match self.mq_rx.recv_timeout_async(wait).await {
+                Ok(message) => self.append(message),
+                Err(RecvTimeoutError::Timeout) => return,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.close();
+                    return;
                 }
-                // Handle other message types if needed
-                other => {
-                    log::debug!("Received other ChannelMessage type: {:?}", other);
+            }
+        }
+        for _ in 0..MAX_SPOOL_BATCH {
+            match self.mq_rx.try_recv() {
+                Ok(message) => self.append(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.close();
+                    break;
                 }
             }
         }
+    }
 
-        log::warn!("mq_rx closed, shutting down lavin MQ loop");
-        break 'outer;
+    /// Keeps spooling incoming messages while waiting for the broker.
+    async fn receive_for(&mut self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            if let Some(shutdown_deadline) = self.shutdown_deadline {
+                tokio::time::sleep(
+                    remaining.min(shutdown_deadline.saturating_duration_since(Instant::now())),
+                )
+                .await;
+                break;
+            }
+            self.receive(Some(remaining)).await;
+        }
     }
 
-    Ok(())
+    fn close(&mut self) {
+        log::info!(
+            "mq_rx closed, publishing what is left for up to {:?}",
+            self.shutdown_timeout
+        );
+        self.mq_rx_closed = true;
+        self.shutdown_deadline = Some(Instant::now() + self.shutdown_timeout);
+        if let Some(account_coalescer) = &mut self.account_coalescer {
+            let held = account_coalescer.flush();
+            self.spool_messages(held);
+        }
+        // spooled rather than lost, with the commitment their slot is known to have
+        let held = self.commitment_buffer.flush();
+        if !held.is_empty() {
+            log::warn!(
+                "Spooling {} MQ messages of slots which are not {} yet as processed",
+                held.len(),
+                self.commitment_buffer.commitment()
+            );
+            self.spool_released(held, CommitmentLevel::Processed);
+        }
+    }
+
+    fn shutdown_expired(&self) -> bool {
+        self.shutdown_deadline
+            .is_some_and(|shutdown_deadline| Instant::now() >= shutdown_deadline)
+    }
+
+    fn append(&mut self, message: ChannelMessage) {
+        let messages = match &mut self.account_coalescer {
+            Some(account_coalescer) => account_coalescer.push(message),
+            None => vec![message],
+        };
+        self.spool_messages(messages);
+    }
+
+    fn spool_messages(&mut self, messages: Vec<ChannelMessage>) {
+        let released = messages
+            .into_iter()
+            .flat_map(|message| self.commitment_buffer.push(message))
+            .collect();
+        self.spool_released(released, self.commitment_buffer.commitment());
+    }
+
+    // `commitment` is the commitment the slots of the messages reached
+    fn spool_released(&mut self, messages: Vec<ChannelMessage>, commitment: CommitmentLevel) {
+        let entries = messages
+            .into_iter()
+            .flat_map(|message| {
+                spool_entries(
+                    message,
+                    &self.routing_keys,
+                    self.encoding,
+                    self.compression.as_ref(),
+                    commitment,
+                    self.dead_letter.as_ref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        for entry in entries {
+            match self.spool.append(&entry) {
+                Ok(true) => {}
+                Ok(false) => {
+                    log::error!(
+                        "MQ spool is full ({} bytes), dropping message for {}",
+                        self.spool.size(),
+                        entry.routing_key
+                    );
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to write message for {} to MQ spool: {e}",
+                        entry.routing_key
+                    );
+                }
+            }
+        }
+        MQ_SPOOL_SIZE.set(self.spool.size() as i64);
+    }
+}
+
+fn spool_entries(
+    message: ChannelMessage,
+    routing_keys: &ConfigRoutingKeys,
+    encoding: PayloadEncoding,
+    compression: Option<&ConfigCompression>,
+    commitment: CommitmentLevel,
+    dead_letter: Option<&DeadLetter>,
+) -> Vec<SpoolEntry> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    // events are released by the commitment buffer once their slot reaches the commitment
+    let commitment = HeaderValue::String(commitment.to_string());
+
+    let (keys, message_id, headers, payload) = match &message {
+        ChannelMessage::Transaction(tx) => {
+            let program_ids = tx
+                .program_ids()
+                .iter()
+                .map(|program_id| program_id.to_string())
+                .collect();
+            let mut headers = BTreeMap::from([
+                (
+                    SLOT_HEADER,
+                    HeaderValue::Integer(tx.slot_identifier.slot as i64),
+                ),
+                (COMMITMENT_HEADER, commitment),
+                (PROGRAM_IDS_HEADER, HeaderValue::Strings(program_ids)),
+            ]);
+            if let Some(error) = &tx.transaction_meta.error {
+                headers.extend(TransactionErrorCategory::new(error).headers());
+            }
+            (
+                transaction_routing_keys(&routing_keys.transaction, tx),
+                tx.signatures
+                    .first()
+                    .map(|signature| signature.to_string())
+                    .unwrap_or_default(),
+                headers,
+                encode_transaction(tx, encoding),
+            )
+        }
+        &ChannelMessage::Account(ref account_data, slot, is_startup) => (
+            vec![account_routing_key(
+                &routing_keys.account,
+                account_data,
+                slot,
+            )],
+            format!("{}:{}", account_data.pubkey, account_data.write_version),
+            BTreeMap::from([
+                (SLOT_HEADER, HeaderValue::Integer(slot as i64)),
+                (COMMITMENT_HEADER, commitment),
+                (
+                    OWNER_HEADER,
+                    HeaderValue::String(account_data.account.owner.to_string()),
+                ),
+            ]),
+            encode_account(account_data, slot, is_startup, encoding),
+        ),
+        ChannelMessage::BlockMeta(block_meta) => (
+            vec![block_meta_routing_key(
+                &routing_keys.block_meta,
+                block_meta.slot,
+            )],
+            block_meta.blockhash.clone(),
+            BTreeMap::from([
+                (SLOT_HEADER, HeaderValue::Integer(block_meta.slot as i64)),
+                (COMMITMENT_HEADER, commitment),
+            ]),
+            encode_block_meta(block_meta, encoding),
+        ),
+        &ChannelMessage::Slot(slot, parent, commitment_config) => (
+            vec![slot_routing_key(
+                &routing_keys.slot,
+                slot,
+                commitment_config,
+            )],
+            format!("{slot}:{}", commitment_config.commitment),
+            BTreeMap::from([
+                (SLOT_HEADER, HeaderValue::Integer(slot as i64)),
+                (
+                    COMMITMENT_HEADER,
+                    HeaderValue::String(commitment_config.commitment.to_string()),
+                ),
+            ]),
+            encode_slot(
+                &SlotMeta {
+                    slot,
+                    parent,
+                    commitment_config,
+                },
+                encoding,
+            ),
+        ),
+        ChannelMessage::Block(block) => (
+            vec![block_routing_key(&routing_keys.block, block.meta.slot)],
+            block.meta.blockhash.clone(),
+            BTreeMap::from([
+                (SLOT_HEADER, HeaderValue::Integer(block.meta.slot as i64)),
+                (COMMITMENT_HEADER, commitment),
+            ]),
+            encode_block(block, encoding),
+        ),
+        ChannelMessage::Entry(entry) => (
+            vec![entry_routing_key(&routing_keys.entry, entry.slot)],
+            format!("{}:{}", entry.slot, entry.index),
+            BTreeMap::from([
+                (SLOT_HEADER, HeaderValue::Integer(entry.slot as i64)),
+                (COMMITMENT_HEADER, commitment),
+            ]),
+            encode_entry(entry, encoding),
+        ),
+    };
+
+    let mut headers: BTreeMap<String, HeaderValue> = headers
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    let payload = match payload {
+        Ok(payload) => payload,
+        Err(e) => {
+            MQ_SERIALIZATION_FAILURES.inc();
+            let Some(dead_letter) = dead_letter else {
+                log::error!("Failed to serialize message for {keys:?}: {e}");
+                return vec![];
+            };
+            log::error!("Failed to serialize message for {keys:?}: {e}, dead-lettering it");
+            // the message cannot be serialized, its debug output is the best we can keep
+            let properties = MessageProperties {
+                content_type: TEXT_CONTENT_TYPE.to_string(),
+                content_encoding: None,
+                message_id,
+                timestamp,
+                headers,
+            };
+            let payload = format!("{message:?}").into_bytes();
+            return keys
+                .into_iter()
+                .map(|routing_key| {
+                    let entry = SpoolEntry {
+                        routing_key,
+                        dead_letter: false,
+                        properties: properties.clone(),
+                        payload: payload.clone(),
+                    };
+                    dead_letter.entry(&entry, format!("serialization failed: {e}"))
+                })
+                .collect();
+        }
+    };
+    headers.insert(
+        MESSAGE_TYPE_HEADER.to_string(),
+        HeaderValue::String(payload.message_type.to_string()),
+    );
+    let (data, content_encoding) = match compression {
+        Some(compression) => compress(payload.data, compression),
+        None => (payload.data, None),
+    };
+    let properties = MessageProperties {
+        content_type: payload.content_type,
+        content_encoding,
+        message_id,
+        timestamp,
+        headers,
+    };
+    keys.into_iter()
+        .map(|routing_key| SpoolEntry {
+            routing_key,
+            dead_letter: false,
+            properties: properties.clone(),
+            payload: data.clone(),
+        })
+        .collect()
 }
 
-async fn publish_message(channel: &lapin::Channel, queue: &str, payload: &[u8]) -> Result<()> {
-    let confirm = channel
-        .basic_publish(
-            "",
-            queue,
-            BasicPublishOptions::default(),
-            payload,
-            BasicProperties::default(),
-        )
-        .await?
-        .await?;
+/// Where poison messages are published, see `ConfigDeadLetter`.
+struct DeadLetter {
+    routing_key: String,
+    max_nacks: u32,
+    // exchange the messages were meant for, kept in their headers
+    original_exchange: String,
+}
 
-    if confirm.is_nack() {
-        return Err(anyhow::anyhow!("Broker did not acknowledge message"));
+impl DeadLetter {
+    fn new(topology: &ConfigTopology) -> Option<Self> {
+        topology.dead_letter.as_ref().map(|dead_letter| Self {
+            routing_key: dead_letter.routing_key.clone(),
+            max_nacks: dead_letter.max_nacks.max(1),
+            original_exchange: topology.exchange.clone(),
+        })
     }
 
-    Ok(())
+    /// Dead-letter version of the entry, its headers tell why and where it was going.
+    fn entry(&self, entry: &SpoolEntry, error: String) -> SpoolEntry {
+        let mut properties = entry.properties.clone();
+        properties
+            .headers
+            .insert(ERROR_HEADER.to_string(), HeaderValue::String(error));
+        properties.headers.insert(
+            ORIGINAL_EXCHANGE_HEADER.to_string(),
+            HeaderValue::String(self.original_exchange.clone()),
+        );
+        properties.headers.insert(
+            ORIGINAL_ROUTING_KEY_HEADER.to_string(),
+            HeaderValue::String(entry.routing_key.clone()),
+        );
+        SpoolEntry {
+            routing_key: self.routing_key.clone(),
+            dead_letter: true,
+            properties,
+            payload: entry.payload.clone(),
+        }
+    }
 }
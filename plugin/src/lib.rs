@@ -1,3 +1,15 @@
+pub mod account_coalescer;
+pub mod brokers;
+pub mod commitment_buffer;
 pub mod config;
+pub mod filters;
+pub mod filters_reload;
 pub mod quic_plugin;
-pub mod lavin_mq_loop;
\ No newline at end of file
+pub mod lavin_mq_loop;
+#[cfg(feature = "test-utils")]
+pub mod memory_broker;
+pub mod mq_channel;
+pub mod mq_publisher;
+pub mod payload;
+pub mod routing;
+pub mod spool;
@@ -0,0 +1,196 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use lapin::publisher_confirm::Confirmation;
+
+use crate::{
+    config::{ConfigTopology, ExchangeType},
+    mq_publisher::{Confirm, Connector, Publisher},
+    spool::{MessageProperties, SpoolEntry},
+};
+
+/// In-memory stand-in for an AMQP broker, so the MQ loop can be tested without one.
+///
+/// Clones share the same state: give one to `run_mq_loop` and inspect the other.
+/// Failures are scripted before or while the loop runs: refused connections,
+/// nacked publishes, messages which are always nacked and connections dropped after a number
+/// of publishes.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBroker {
+    state: Arc<Mutex<BrokerState>>,
+}
+
+#[derive(Debug, Default)]
+struct BrokerState {
+    connections: Vec<String>,
+    exchanges: Vec<(String, ExchangeType)>,
+    queues: Vec<String>,
+    published: Vec<PublishedMessage>,
+    refused_connections: usize,
+    nacks: usize,
+    nacked_message_ids: Vec<String>,
+    publishes_before_disconnect: Option<usize>,
+}
+
+/// A message the broker acked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishedMessage {
+    /// Url of the connection it was published on.
+    pub url: String,
+    pub exchange: String,
+    pub routing_key: String,
+    pub properties: MessageProperties,
+    pub payload: Vec<u8>,
+}
+
+impl MemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, BrokerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The next `count` connections fail.
+    pub fn refuse_connections(&self, count: usize) {
+        self.state().refused_connections = count;
+    }
+
+    /// The next `count` publishes are nacked.
+    pub fn nack(&self, count: usize) {
+        self.state().nacks = count;
+    }
+
+    /// Every publish of the message with this id is nacked.
+    pub fn always_nack(&self, message_id: &str) {
+        self.state().nacked_message_ids.push(message_id.to_string());
+    }
+
+    /// The connection fails on the publish after the next `count` ones.
+    pub fn disconnect_after(&self, count: usize) {
+        self.state().publishes_before_disconnect = Some(count);
+    }
+
+    /// Urls of the successful connections, in order.
+    pub fn connections(&self) -> Vec<String> {
+        self.state().connections.clone()
+    }
+
+    /// Exchanges declared with their type, the default exchange is never declared.
+    pub fn exchanges(&self) -> Vec<(String, ExchangeType)> {
+        self.state().exchanges.clone()
+    }
+
+    pub fn queues(&self) -> Vec<String> {
+        self.state().queues.clone()
+    }
+
+    /// Acked messages, in publish order.
+    pub fn published(&self) -> Vec<PublishedMessage> {
+        self.state().published.clone()
+    }
+}
+
+impl Connector for MemoryBroker {
+    type Publisher = MemoryPublisher;
+
+    async fn connect(
+        &mut self,
+        url: &str,
+        topology: &ConfigTopology,
+    ) -> anyhow::Result<MemoryPublisher> {
+        let mut state = self.state();
+        if state.refused_connections > 0 {
+            state.refused_connections -= 1;
+            anyhow::bail!("connection to {url} refused");
+        }
+        state.connections.push(url.to_string());
+
+        let dead_letter = topology.dead_letter.as_ref().map(|dead_letter| {
+            (
+                &dead_letter.exchange,
+                dead_letter.exchange_type,
+                &dead_letter.queues,
+            )
+        });
+        let exchanges =
+            std::iter::once((&topology.exchange, topology.exchange_type, &topology.queues))
+                .chain(dead_letter);
+        for (exchange, exchange_type, queues) in exchanges {
+            if !exchange.is_empty() && !state.exchanges.iter().any(|(name, _)| name == exchange) {
+                state.exchanges.push((exchange.clone(), exchange_type));
+            }
+            for queue in queues {
+                if !state.queues.contains(&queue.name) {
+                    state.queues.push(queue.name.clone());
+                }
+            }
+        }
+
+        Ok(MemoryPublisher {
+            broker: self.clone(),
+            url: url.to_string(),
+            exchange: topology.exchange.clone(),
+            dead_letter_exchange: topology
+                .dead_letter
+                .as_ref()
+                .map(|dead_letter| dead_letter.exchange.clone()),
+            last_delivery_tag: 0,
+        })
+    }
+}
+
+/// Connection to a `MemoryBroker`.
+#[derive(Debug)]
+pub struct MemoryPublisher {
+    broker: MemoryBroker,
+    url: String,
+    exchange: String,
+    dead_letter_exchange: Option<String>,
+    last_delivery_tag: u64,
+}
+
+impl Publisher for MemoryPublisher {
+    async fn publish(&mut self, entry: &SpoolEntry) -> anyhow::Result<(u64, Confirm)> {
+        let mut state = self.broker.state();
+        if let Some(count) = state.publishes_before_disconnect {
+            if count == 0 {
+                state.publishes_before_disconnect = None;
+                anyhow::bail!("connection to {} reset", self.url);
+            }
+            state.publishes_before_disconnect = Some(count - 1);
+        }
+        self.last_delivery_tag += 1;
+
+        let confirmation = if state.nacks > 0 {
+            state.nacks -= 1;
+            Confirmation::Nack(None)
+        } else if state
+            .nacked_message_ids
+            .contains(&entry.properties.message_id)
+        {
+            Confirmation::Nack(None)
+        } else {
+            let exchange = match &self.dead_letter_exchange {
+                Some(dead_letter_exchange) if entry.dead_letter => dead_letter_exchange,
+                _ => &self.exchange,
+            };
+            state.published.push(PublishedMessage {
+                url: self.url.clone(),
+                exchange: exchange.clone(),
+                routing_key: entry.routing_key.clone(),
+                properties: entry.properties.clone(),
+                payload: entry.payload.clone(),
+            });
+            Confirmation::Ack(None)
+        };
+        Ok((
+            self.last_delivery_tag,
+            Box::pin(std::future::ready(Ok(confirmation))),
+        ))
+    }
+
+    async fn close(self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
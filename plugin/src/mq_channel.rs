@@ -0,0 +1,284 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{RecvTimeoutError, SendError, TryRecvError},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use prometheus::{opts, register_int_counter_vec, register_int_gauge, IntCounterVec, IntGauge};
+use quic_geyser_common::channel_message::ChannelMessage;
+use tokio::sync::Notify;
+
+use crate::config::{ConfigChannel, OverflowPolicy};
+
+lazy_static::lazy_static! {
+    static ref MQ_CHANNEL_DEPTH: IntGauge =
+       register_int_gauge!(opts!("quic_plugin_mq_channel_depth", "Number of messages waiting in the channel between the plugin and the MQ loop")).unwrap();
+
+    static ref MQ_CHANNEL_DROPPED: IntCounterVec =
+       register_int_counter_vec!(opts!("quic_plugin_mq_channel_dropped", "Number of messages dropped because the MQ channel was full"), &["kind"]).unwrap();
+}
+
+/// Bounded channel between the plugin callbacks and the MQ loop.
+/// When it is full, sending applies the configured `OverflowPolicy`.
+pub fn mq_channel(config: &ConfigChannel) -> (MqSender, MqReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            messages: VecDeque::new(),
+            account_updates: 0,
+            senders: 1,
+            receiver_alive: true,
+            overflowing: false,
+        }),
+        not_empty: Condvar::new(),
+        not_empty_async: Notify::new(),
+        not_full: Condvar::new(),
+        capacity: config.capacity.max(1),
+        overflow: config.overflow,
+    });
+    (
+        MqSender {
+            shared: shared.clone(),
+        },
+        MqReceiver { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    not_empty: Condvar,
+    // wakes `MqReceiver::recv_timeout_async`, keeps a wakeup sent while nobody waits
+    not_empty_async: Notify,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: OverflowPolicy,
+}
+
+impl Shared {
+    // a panicking sender cannot leave the queue inconsistent, so poisoning is ignored
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    messages: VecDeque<ChannelMessage>,
+    // account updates in `messages`, so shedding does not scan a queue without any
+    account_updates: usize,
+    senders: usize,
+    receiver_alive: bool,
+    // set while the overflow policy applies, to log it once
+    overflowing: bool,
+}
+
+impl State {
+    fn push(&mut self, message: ChannelMessage) {
+        if is_account_update(&message) {
+            self.account_updates += 1;
+        }
+        self.messages.push_back(message);
+        MQ_CHANNEL_DEPTH.set(self.messages.len() as i64);
+    }
+
+    fn pop(&mut self) -> Option<ChannelMessage> {
+        let message = self.messages.pop_front()?;
+        if is_account_update(&message) {
+            self.account_updates -= 1;
+        }
+        MQ_CHANNEL_DEPTH.set(self.messages.len() as i64);
+        Some(message)
+    }
+
+    fn remove_oldest_account_update(&mut self) -> Option<ChannelMessage> {
+        if self.account_updates == 0 {
+            return None;
+        }
+        let index = self.messages.iter().position(is_account_update)?;
+        let message = self.messages.remove(index)?;
+        self.account_updates -= 1;
+        MQ_CHANNEL_DEPTH.set(self.messages.len() as i64);
+        Some(message)
+    }
+}
+
+fn is_account_update(message: &ChannelMessage) -> bool {
+    matches!(message, ChannelMessage::Account(..))
+}
+
+fn count_dropped(message: &ChannelMessage) {
+    let kind = match message {
+        ChannelMessage::Account(..) => "account",
+        ChannelMessage::Slot(..) => "slot",
+        ChannelMessage::BlockMeta(_) => "block_meta",
+        ChannelMessage::Transaction(_) => "transaction",
+        ChannelMessage::Block(_) => "block",
+        ChannelMessage::Entry(_) => "entry",
+    };
+    MQ_CHANNEL_DROPPED.with_label_values(&[kind]).inc();
+}
+
+#[derive(Debug)]
+pub struct MqSender {
+    shared: Arc<Shared>,
+}
+
+impl MqSender {
+    /// Sends a message to the MQ loop, applying the overflow policy if the channel is full.
+    /// Dropped messages are counted, not reported as errors, sending only fails once the
+    /// MQ loop is gone.
+    // same error as `std::sync::mpsc::Sender::send`, which the callers used before
+    #[allow(clippy::result_large_err)]
+    pub fn send(&self, message: ChannelMessage) -> Result<(), SendError<ChannelMessage>> {
+        let shared = &self.shared;
+        let mut state = shared.lock();
+        loop {
+            if !state.receiver_alive {
+                return Err(SendError(message));
+            }
+            if state.messages.len() < shared.capacity {
+                break;
+            }
+            if !state.overflowing {
+                state.overflowing = true;
+                log::warn!(
+                    "MQ channel is full ({} messages), applying the {:?} overflow policy",
+                    shared.capacity,
+                    shared.overflow
+                );
+            }
+            match shared.overflow {
+                OverflowPolicy::Block => {}
+                OverflowPolicy::DropNewest => {
+                    count_dropped(&message);
+                    return Ok(());
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = state.pop() {
+                        count_dropped(&oldest);
+                    }
+                    continue;
+                }
+                OverflowPolicy::ShedAccounts => {
+                    if let Some(account_update) = state.remove_oldest_account_update() {
+                        count_dropped(&account_update);
+                        continue;
+                    }
+                    if is_account_update(&message) {
+                        count_dropped(&message);
+                        return Ok(());
+                    }
+                }
+            }
+            state = shared
+                .not_full
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        state.push(message);
+        shared.not_empty.notify_one();
+        shared.not_empty_async.notify_one();
+        Ok(())
+    }
+}
+
+impl Clone for MqSender {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for MqSender {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+            self.shared.not_empty_async.notify_one();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MqReceiver {
+    shared: Arc<Shared>,
+}
+
+impl MqReceiver {
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ChannelMessage, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(message) = self.take(&mut state) {
+                return Ok(message);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .not_empty
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// `recv_timeout` for async callers, which waits without blocking the runtime thread.
+    pub async fn recv_timeout_async(
+        &self,
+        timeout: Duration,
+    ) -> Result<ChannelMessage, RecvTimeoutError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match self.try_recv() {
+                Ok(message) => return Ok(message),
+                Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => {}
+            }
+            let notified = self.shared.not_empty_async.notified();
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<ChannelMessage, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.take(&mut state) {
+            Some(message) => Ok(message),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    fn take(&self, state: &mut State) -> Option<ChannelMessage> {
+        let message = state.pop()?;
+        if state.messages.is_empty() {
+            state.overflowing = false;
+        }
+        self.shared.not_full.notify_one();
+        Some(message)
+    }
+}
+
+impl Drop for MqReceiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.messages.clear();
+        state.account_updates = 0;
+        MQ_CHANNEL_DEPTH.set(0);
+        self.shared.not_full.notify_all();
+    }
+}
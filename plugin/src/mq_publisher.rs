@@ -1,49 +1,288 @@
+use std::{future::Future, pin::Pin};
+
+use anyhow::{anyhow, Context};
 use lapin::{
-    options::{BasicPublishOptions, ExchangeDeclareOptions},
-    types::FieldTable,
-    BasicProperties, Connection, ExchangeKind, Result,
+    options::{
+        BasicPublishOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions,
+        QueueDeclareOptions,
+    },
+    publisher_confirm::Confirmation,
+    types::{AMQPValue, FieldArray, FieldTable},
+    BasicProperties, Connection, ConnectionProperties, ExchangeKind,
+};
+use serde_json::Value;
+
+use crate::{
+    config::{ConfigQueue, ConfigTopology, ExchangeType},
+    spool::{HeaderValue, MessageProperties, SpoolEntry},
 };
-use lapin::ConnectionProperties;
 
+// persistent messages survive a broker restart when they are in a durable queue
+const PERSISTENT_DELIVERY_MODE: u8 = 2;
+// AMQP reply code of a normal close
+const REPLY_SUCCESS: u16 = 200;
+
+/// Broker confirm of a published message.
+pub type Confirm = Pin<Box<dyn Future<Output = lapin::Result<Confirmation>> + Send>>;
+
+/// Opens publishers on a broker, `AmqpConnector` for real brokers.
+pub trait Connector {
+    type Publisher: Publisher;
+
+    /// Connects to the broker and declares the topology.
+    fn connect(
+        &mut self,
+        url: &str,
+        topology: &ConfigTopology,
+    ) -> impl Future<Output = anyhow::Result<Self::Publisher>>;
+}
+
+/// Publishes spooled messages on a broker connection.
+pub trait Publisher {
+    /// Sends a message without waiting for the broker.
+    /// Returns the delivery tag of the message and the confirm to await.
+    fn publish(
+        &mut self,
+        entry: &SpoolEntry,
+    ) -> impl Future<Output = anyhow::Result<(u64, Confirm)>>;
+
+    /// Closes the connection, unconfirmed messages stay in the spool.
+    fn close(self) -> impl Future<Output = anyhow::Result<()>>;
+}
+
+/// Connects to AMQP brokers with `MQPublisher`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AmqpConnector;
 
+impl Connector for AmqpConnector {
+    type Publisher = MQPublisher;
+
+    async fn connect(
+        &mut self,
+        url: &str,
+        topology: &ConfigTopology,
+    ) -> anyhow::Result<MQPublisher> {
+        MQPublisher::new(url, topology).await
+    }
+}
+
+/// Publisher on a single AMQP channel with publisher confirms enabled.
 #[derive(Debug)]
 pub struct MQPublisher {
+    connection: Connection,
     channel: lapin::Channel,
     exchange_name: String,
+    dead_letter_exchange_name: Option<String>,
+    last_delivery_tag: u64,
 }
 
 impl MQPublisher {
-    pub async fn new(amqp_uri: &str, exchange_name: &str) -> anyhow::Result<Self> {
-        // Use Connection directly
-        let conn = Connection::connect(amqp_uri, ConnectionProperties::default()).await?;
+    /// Connects to the broker and declares the exchange, queues and bindings of the topology.
+    pub async fn new(amqp_uri: &str, topology: &ConfigTopology) -> anyhow::Result<Self> {
+        let connection = Connection::connect(amqp_uri, ConnectionProperties::default())
+            .await
+            .context("connecting to AMQP")?;
 
-        let channel = conn.create_channel().await?;
+        let channel = connection
+            .create_channel()
+            .await
+            .context("creating channel")?;
 
+        // without publisher confirms the broker never tells us a message is safe
         channel
-            .exchange_declare(
-                exchange_name,
-                ExchangeKind::Direct,
-                ExchangeDeclareOptions::default(),
-                FieldTable::default(),
-            )
-            .await?;
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .context("enabling publisher confirms")?;
+
+        declare_topology(&channel, topology).await?;
 
         Ok(Self {
+            connection,
             channel,
-            exchange_name: exchange_name.to_string(),
+            exchange_name: topology.exchange.clone(),
+            dead_letter_exchange_name: topology
+                .dead_letter
+                .as_ref()
+                .map(|dead_letter| dead_letter.exchange.clone()),
+            last_delivery_tag: 0,
         })
     }
+}
 
-    pub async fn publish_message(&self, routing_key: &str, data: Vec<u8>) -> Result<()> {
-        self.channel
+impl Publisher for MQPublisher {
+    async fn publish(&mut self, entry: &SpoolEntry) -> anyhow::Result<(u64, Confirm)> {
+        let properties = basic_properties(&entry.properties);
+        // dead letters spooled before the dead-letter exchange was removed from the config
+        // go to the main exchange
+        let exchange_name = match &self.dead_letter_exchange_name {
+            Some(dead_letter_exchange_name) if entry.dead_letter => dead_letter_exchange_name,
+            _ => &self.exchange_name,
+        };
+        let confirm = self
+            .channel
             .basic_publish(
-                &self.exchange_name,
-                routing_key,
+                exchange_name,
+                &entry.routing_key,
                 BasicPublishOptions::default(),
-                &data,
-                BasicProperties::default(),
+                &entry.payload,
+                properties,
             )
             .await?;
+        // in confirm mode the broker numbers the deliveries of a channel from 1
+        self.last_delivery_tag += 1;
+        Ok((self.last_delivery_tag, Box::pin(confirm)))
+    }
+
+    async fn close(self) -> anyhow::Result<()> {
+        self.channel
+            .close(REPLY_SUCCESS, "plugin shutdown")
+            .await
+            .context("closing channel")?;
+        self.connection
+            .close(REPLY_SUCCESS, "plugin shutdown")
+            .await
+            .context("closing connection")?;
         Ok(())
     }
 }
+
+async fn declare_topology(
+    channel: &lapin::Channel,
+    topology: &ConfigTopology,
+) -> anyhow::Result<()> {
+    declare_exchange(
+        channel,
+        &topology.exchange,
+        topology.exchange_type,
+        &topology.queues,
+    )
+    .await?;
+    if let Some(dead_letter) = &topology.dead_letter {
+        declare_exchange(
+            channel,
+            &dead_letter.exchange,
+            dead_letter.exchange_type,
+            &dead_letter.queues,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Declares an exchange with its queues and bindings.
+async fn declare_exchange(
+    channel: &lapin::Channel,
+    exchange: &str,
+    exchange_type: ExchangeType,
+    queues: &[ConfigQueue],
+) -> anyhow::Result<()> {
+    // the default exchange always exists and cannot be bound to
+    let use_default_exchange = exchange.is_empty();
+    if !use_default_exchange {
+        channel
+            .exchange_declare(
+                exchange,
+                exchange_kind(exchange_type),
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .with_context(|| format!("declaring exchange {exchange}"))?;
+    }
+
+    for queue in queues {
+        let arguments = field_table(&queue.all_arguments())
+            .with_context(|| format!("arguments of queue {}", queue.name))?;
+        channel
+            .queue_declare(
+                &queue.name,
+                QueueDeclareOptions {
+                    durable: queue.durable || queue.is_stream(),
+                    ..Default::default()
+                },
+                arguments,
+            )
+            .await
+            .with_context(|| format!("declaring queue {}", queue.name))?;
+
+        if use_default_exchange {
+            continue;
+        }
+        let bindings = if queue.bindings.is_empty() {
+            vec![queue.name.clone()]
+        } else {
+            queue.bindings.clone()
+        };
+        for binding in bindings {
+            channel
+                .queue_bind(
+                    &queue.name,
+                    exchange,
+                    &binding,
+                    QueueBindOptions::default(),
+                    FieldTable::default(),
+                )
+                .await
+                .with_context(|| format!("binding queue {} with {binding}", queue.name))?;
+        }
+    }
+    Ok(())
+}
+
+fn exchange_kind(exchange_type: ExchangeType) -> ExchangeKind {
+    match exchange_type {
+        ExchangeType::Direct => ExchangeKind::Direct,
+        ExchangeType::Topic => ExchangeKind::Topic,
+        ExchangeType::Fanout => ExchangeKind::Fanout,
+    }
+}
+
+fn basic_properties(properties: &MessageProperties) -> BasicProperties {
+    let mut headers = FieldTable::default();
+    for (name, value) in &properties.headers {
+        let value = match value {
+            HeaderValue::Integer(value) => AMQPValue::LongLongInt(*value),
+            HeaderValue::String(value) => AMQPValue::LongString(value.clone().into()),
+            HeaderValue::Strings(values) => AMQPValue::FieldArray(FieldArray::from(
+                values
+                    .iter()
+                    .map(|value| AMQPValue::LongString(value.clone().into()))
+                    .collect::<Vec<_>>(),
+            )),
+        };
+        headers.insert(name.clone().into(), value);
+    }
+    let basic_properties = BasicProperties::default()
+        .with_delivery_mode(PERSISTENT_DELIVERY_MODE)
+        .with_content_type(properties.content_type.as_str().into())
+        .with_message_id(properties.message_id.as_str().into())
+        .with_timestamp(properties.timestamp)
+        .with_headers(headers);
+    match &properties.content_encoding {
+        Some(content_encoding) => {
+            basic_properties.with_content_encoding(content_encoding.as_str().into())
+        }
+        None => basic_properties,
+    }
+}
+
+/// Converts json arguments from the config into an AMQP field table.
+pub fn field_table(arguments: &serde_json::Map<String, Value>) -> anyhow::Result<FieldTable> {
+    let mut table = FieldTable::default();
+    for (key, value) in arguments {
+        let value = match value {
+            Value::Bool(b) => AMQPValue::Boolean(*b),
+            Value::Number(number) => match number.as_i64() {
+                Some(number) => AMQPValue::LongLongInt(number),
+                None => AMQPValue::Double(number.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => AMQPValue::LongString(s.clone().into()),
+            _ => return Err(anyhow!("unsupported value for argument {key}: {value}")),
+        };
+        table.insert(key.clone().into(), value);
+    }
+    Ok(table)
+}
@@ -0,0 +1,198 @@
+use quic_geyser_common::{
+    channel_message::AccountData,
+    compression::CompressionType,
+    protobuf,
+    types::{
+        block::Block,
+        block_meta::{BlockMeta, SlotMeta},
+        entry::Entry,
+        transaction::Transaction,
+    },
+};
+use solana_sdk::{clock::Slot, instruction::InstructionError, transaction::TransactionError};
+
+use crate::{config::ConfigCompression, spool::HeaderValue};
+
+// the wire format is shared with the consumers
+pub use quic_geyser_common::mq_payload::*;
+
+/// Error of a failed transaction as published in its headers, so failures can be counted by kind
+/// without decoding the payloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionErrorCategory {
+    /// `TransactionError` variant, e.g. `InstructionError` or `InsufficientFundsForFee`.
+    pub transaction_error: String,
+    /// Index of the failed instruction and its `InstructionError` variant.
+    pub instruction_error: Option<(u8, String)>,
+    /// Code of a `Custom` program error.
+    pub custom_error: Option<u32>,
+}
+
+impl TransactionErrorCategory {
+    pub fn new(error: &TransactionError) -> Self {
+        let (instruction_error, custom_error) = match error {
+            TransactionError::InstructionError(index, instruction_error) => (
+                Some((*index, variant_name(instruction_error))),
+                match instruction_error {
+                    InstructionError::Custom(code) => Some(*code),
+                    _ => None,
+                },
+            ),
+            _ => (None, None),
+        };
+        Self {
+            transaction_error: variant_name(error),
+            instruction_error,
+            custom_error,
+        }
+    }
+
+    pub fn headers(&self) -> Vec<(&'static str, HeaderValue)> {
+        let mut headers = vec![(
+            TRANSACTION_ERROR_HEADER,
+            HeaderValue::String(self.transaction_error.clone()),
+        )];
+        if let Some((index, instruction_error)) = &self.instruction_error {
+            headers.push((
+                INSTRUCTION_INDEX_HEADER,
+                HeaderValue::Integer(*index as i64),
+            ));
+            headers.push((
+                INSTRUCTION_ERROR_HEADER,
+                HeaderValue::String(instruction_error.clone()),
+            ));
+        }
+        if let Some(code) = self.custom_error {
+            headers.push((CUSTOM_ERROR_HEADER, HeaderValue::Integer(code as i64)));
+        }
+        headers
+    }
+}
+
+// what the debug output of an enum starts with
+fn variant_name(value: &impl std::fmt::Debug) -> String {
+    let debug = format!("{value:?}");
+    match debug.find(|c: char| !c.is_alphanumeric()) {
+        Some(end) => debug[..end].to_string(),
+        None => debug,
+    }
+}
+
+/// Serialized message with the AMQP content type describing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub content_type: String,
+    pub message_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+pub fn encode_transaction(
+    transaction: &Transaction,
+    encoding: PayloadEncoding,
+) -> anyhow::Result<Payload> {
+    let data = match encoding {
+        PayloadEncoding::Protobuf => protobuf::encode_transaction(transaction),
+        _ => serialize(transaction, encoding)?,
+    };
+    Ok(Payload {
+        content_type: content_type(encoding, TRANSACTION_MESSAGE_TYPE),
+        message_type: TRANSACTION_MESSAGE_TYPE,
+        data,
+    })
+}
+
+pub fn encode_account(
+    account_data: &AccountData,
+    slot: Slot,
+    is_startup: bool,
+    encoding: PayloadEncoding,
+) -> anyhow::Result<Payload> {
+    let data = match encoding {
+        PayloadEncoding::Protobuf => protobuf::encode_account(account_data, slot, is_startup),
+        _ => serialize_account(account_data, slot, is_startup, encoding)?,
+    };
+    Ok(Payload {
+        content_type: content_type(encoding, ACCOUNT_MESSAGE_TYPE),
+        message_type: ACCOUNT_MESSAGE_TYPE,
+        data,
+    })
+}
+
+pub fn encode_block_meta(
+    block_meta: &BlockMeta,
+    encoding: PayloadEncoding,
+) -> anyhow::Result<Payload> {
+    let data = match encoding {
+        PayloadEncoding::Protobuf => protobuf::encode_block_meta(block_meta),
+        _ => serialize(block_meta, encoding)?,
+    };
+    Ok(Payload {
+        content_type: content_type(encoding, BLOCK_META_MESSAGE_TYPE),
+        message_type: BLOCK_META_MESSAGE_TYPE,
+        data,
+    })
+}
+
+pub fn encode_slot(slot_meta: &SlotMeta, encoding: PayloadEncoding) -> anyhow::Result<Payload> {
+    let data = match encoding {
+        PayloadEncoding::Protobuf => protobuf::encode_slot(slot_meta),
+        _ => serialize(slot_meta, encoding)?,
+    };
+    Ok(Payload {
+        content_type: content_type(encoding, SLOT_MESSAGE_TYPE),
+        message_type: SLOT_MESSAGE_TYPE,
+        data,
+    })
+}
+
+pub fn encode_entry(entry: &Entry, encoding: PayloadEncoding) -> anyhow::Result<Payload> {
+    let data = match encoding {
+        PayloadEncoding::Protobuf => protobuf::encode_entry(entry),
+        _ => serialize(entry, encoding)?,
+    };
+    Ok(Payload {
+        content_type: content_type(encoding, ENTRY_MESSAGE_TYPE),
+        message_type: ENTRY_MESSAGE_TYPE,
+        data,
+    })
+}
+
+/// Transactions and accounts of the block stay compressed by the block builder.
+pub fn encode_block(block: &Block, encoding: PayloadEncoding) -> anyhow::Result<Payload> {
+    let data = match encoding {
+        PayloadEncoding::Protobuf => protobuf::encode_block(block),
+        _ => serialize_block(block, encoding)?,
+    };
+    Ok(Payload {
+        content_type: content_type(encoding, BLOCK_MESSAGE_TYPE),
+        message_type: BLOCK_MESSAGE_TYPE,
+        data,
+    })
+}
+
+/// Compresses payloads of at least `min_size` bytes, returns the content encoding if it did.
+pub fn compress(data: Vec<u8>, compression: &ConfigCompression) -> (Vec<u8>, Option<String>) {
+    if data.len() < compression.min_size || compression.compression_type == CompressionType::None {
+        return (data, None);
+    }
+    let compressed = compression.compression_type.compress(&data);
+    // incompressible payloads are published as they are
+    if compressed.len() >= data.len() {
+        return (data, None);
+    }
+    (compressed, Some(LZ4_CONTENT_ENCODING.to_string()))
+}
+
+/// Protobuf payloads name their message so consumers know which type to decode.
+fn content_type(encoding: PayloadEncoding, message_type: &str) -> String {
+    match encoding {
+        PayloadEncoding::Json | PayloadEncoding::JsonBase64 | PayloadEncoding::JsonBase58 => {
+            JSON_CONTENT_TYPE.to_string()
+        }
+        PayloadEncoding::Bincode => BINCODE_CONTENT_TYPE.to_string(),
+        PayloadEncoding::Protobuf => format!(
+            "{PROTOBUF_CONTENT_TYPE}; messageType={}.{message_type}",
+            protobuf::PACKAGE
+        ),
+    }
+}
@@ -1,6 +1,10 @@
 // src/quic_geyser_plugin.rs
 use crate::config::Config;
+use crate::filters::{Outputs, PluginFilters, SharedFilters};
+use crate::filters_reload::{reloaded_filters, FiltersReloader};
 use crate::lavin_mq_loop::run_lavin_mq_loop;
+use crate::mq_channel::{mq_channel, MqSender};
+use crate::spool::Spool;
 use agave_geyser_plugin_interface::geyser_plugin_interface::{
     GeyserPlugin, GeyserPluginError, ReplicaAccountInfoVersions, ReplicaBlockInfoVersions,
     ReplicaEntryInfoVersions, ReplicaTransactionInfoVersions, Result as PluginResult, SlotStatus,
@@ -8,9 +12,11 @@ use agave_geyser_plugin_interface::geyser_plugin_interface::{
 use quic_geyser_block_builder::block_builder::start_block_building_thread;
 use quic_geyser_common::{
     channel_message::{AccountData, ChannelMessage},
+    filters::TransactionAccounts,
     plugin_error::QuicGeyserError,
     types::{
         block_meta::BlockMeta,
+        entry::Entry,
         slot_identifier::SlotIdentifier,
         transaction::{
             Transaction, TransactionMeta, TransactionTokenBalanceSerializable,
@@ -20,21 +26,35 @@ use quic_geyser_common::{
 };
 use quic_geyser_server::quic_server::QuicServer;
 use solana_sdk::{
-    account::Account, clock::Slot, commitment_config::CommitmentConfig,
+    account::Account, bs58, clock::Slot, commitment_config::CommitmentConfig,
     message::v0::Message, pubkey::Pubkey,
 };
-use std::str::FromStr;
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+// on unload, time given to the MQ loop to close the AMQP connection after its shutdown timeout
+const MQ_CLOSE_GRACE: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Default)]
 pub struct QuicGeyserPlugin {
     quic_server: Option<QuicServer>,
     block_builder_channel: Option<std::sync::mpsc::Sender<ChannelMessage>>,
+    block_builder_handle: Option<JoinHandle<()>>,
+    mq_block_forwarder_handle: Option<JoinHandle<()>>,
     rpc_server_message_channel: Option<std::sync::mpsc::Sender<ChannelMessage>>,
-    mq_sender: Option<std::sync::mpsc::Sender<ChannelMessage>>,
-    mq_thread_handle: Option<std::thread::JoinHandle<()>>,
-    // New fields to store parsed pubkeys
-    account_update_pubkeys: Vec<Pubkey>,
-    transaction_pubkeys: Vec<Pubkey>,
+    mq_sender: Option<MqSender>,
+    mq_thread_handle: Option<JoinHandle<()>>,
+    // disconnected once the MQ thread ends
+    mq_thread_done: Option<Receiver<()>>,
+    mq_shutdown_timeout: Duration,
+    filters: SharedFilters,
+    // the filters reload thread stops once this sender is dropped
+    filters_reload_stop: Option<Sender<()>>,
+    filters_reload_handle: Option<JoinHandle<()>>,
+    entry_notifications: bool,
 }
 
 impl GeyserPlugin for QuicGeyserPlugin {
@@ -42,8 +62,12 @@ impl GeyserPlugin for QuicGeyserPlugin {
         "quic_geyser_plugin"
     }
 
-    fn on_load(&mut self, config_file: &str, _is_reload: bool) -> PluginResult<()> {
-        log::info!("loading quic_geyser plugin");
+    fn on_load(&mut self, config_file: &str, is_reload: bool) -> PluginResult<()> {
+        if is_reload {
+            log::info!("reloading quic_geyser plugin");
+        } else {
+            log::info!("loading quic_geyser plugin");
+        }
         let config = match Config::load_from_file(config_file) {
             Ok(config) => config,
             Err(e) => {
@@ -51,17 +75,44 @@ impl GeyserPlugin for QuicGeyserPlugin {
                 return Err(e);
             }
         };
+        // Use AMQP URLs from either the environment or the config.
+        let amqp_urls = match config.required_broker_urls() {
+            Ok(amqp_urls) => amqp_urls,
+            Err(e) => {
+                log::error!("Error loading config file: {}", e);
+                return Err(e);
+            }
+        };
+        let spool = Spool::open(&config.mq.spool).map_err(|e| {
+            log::error!("Error opening the MQ spool: {e:#}");
+            GeyserPluginError::Custom(e.into())
+        })?;
 
-        // Parse the pubkeys from the top-level config fields.
-        self.account_update_pubkeys = config.account_update_pubkeys
-            .iter()
-            .map(|s| Pubkey::from_str(s).expect("Valid pubkey"))
-            .collect();
-        self.transaction_pubkeys = config.transaction_pubkeys
-            .iter()
-            .map(|s| Pubkey::from_str(s).expect("Valid pubkey"))
-            .collect();
+        let filters = PluginFilters::new(&config).map_err(|e| {
+            log::error!("Invalid filters in config file: {e:#}");
+            GeyserPluginError::ConfigFileReadError {
+                msg: format!("{e:#}"),
+            }
+        })?;
+        if is_reload {
+            let changes = reloaded_filters(&config);
+            if changes.is_empty() {
+                log::info!("Reloaded {config_file}, filters unchanged");
+            } else {
+                log::info!(
+                    "Reloaded filters from {config_file}: {}",
+                    changes.join("; ")
+                );
+            }
+        }
+        self.filters = SharedFilters::new(filters);
+        let filters_reloader = config.filters_reload.clone().map(|filters_reload| {
+            let reloader =
+                FiltersReloader::new(config_file.into(), config.clone(), self.filters.clone());
+            (reloader, filters_reload)
+        });
 
+        self.entry_notifications = config.entry_notifications;
         let compression_type = config.quic_plugin.compression_parameters.compression_type;
         let enable_block_builder = config.quic_plugin.enable_block_builder;
         let build_blocks_with_accounts = config.quic_plugin.build_blocks_with_accounts;
@@ -70,27 +121,49 @@ impl GeyserPlugin for QuicGeyserPlugin {
         let quic_server = QuicServer::new(config.quic_plugin).map_err(|_| {
             GeyserPluginError::Custom(Box::new(QuicGeyserError::ErrorConfiguringServer))
         })?;
+        // threads are only started once nothing can fail, the spool is closed when a failed load
+        // drops it
+        if let Some((reloader, filters_reload)) = filters_reloader {
+            let (stop, stopped) = std::sync::mpsc::channel();
+            self.filters_reload_handle = Some(reloader.start(filters_reload, stopped));
+            self.filters_reload_stop = Some(stop);
+        }
+        // --- Start the MQ server thread
+        let (mq_tx, mq_rx) = mq_channel(&config.mq.channel);
+
         if enable_block_builder {
-            // Start block-building thread if enabled.
+            // Start block-building thread if enabled, built blocks also go to MQ.
             let (sx, rx) = std::sync::mpsc::channel();
-            start_block_building_thread(
+            let (block_tx, block_rx) = std::sync::mpsc::channel::<ChannelMessage>();
+            self.block_builder_handle = Some(start_block_building_thread(
                 rx,
                 quic_server.data_channel_sender.clone(),
+                Some(block_tx),
                 compression_type,
                 build_blocks_with_accounts,
-            );
+                config.entry_notifications,
+            ));
+            let block_mq_tx = mq_tx.clone();
+            self.mq_block_forwarder_handle = Some(std::thread::spawn(move || {
+                for block in block_rx {
+                    if let Err(send_err) = block_mq_tx.send(block) {
+                        log::error!("Failed to send block to MQ server: {send_err}");
+                        break;
+                    }
+                }
+            }));
             self.block_builder_channel = Some(sx);
         }
         self.quic_server = Some(quic_server);
-
-        // --- Start the MQ server thread
-        let (mq_tx, mq_rx) = std::sync::mpsc::channel::<ChannelMessage>();
         self.mq_sender = Some(mq_tx);
 
-        // Use AMQP URL from either the environment or the config.
-        let amqp_url = std::env::var("AMQP_URL").unwrap_or_else(|_| config.amqp_url.clone());
+        let mq_config = config.mq.clone();
+        self.mq_shutdown_timeout = Duration::from_millis(mq_config.shutdown_timeout_ms);
 
+        let (mq_done_tx, mq_done_rx) = std::sync::mpsc::channel::<()>();
         let handle = std::thread::spawn(move || {
+            // dropped when the thread ends, even by a panic
+            let _mq_done_tx = mq_done_tx;
             // Build a single-threaded Tokio runtime.
             let rt = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
@@ -98,19 +171,48 @@ impl GeyserPlugin for QuicGeyserPlugin {
                 .expect("Failed to build tokio runtime for MQ loop");
 
             rt.block_on(async move {
-                if let Err(e) = run_lavin_mq_loop(&amqp_url, mq_rx).await {
+                if let Err(e) = run_lavin_mq_loop(amqp_urls, mq_rx, spool, mq_config).await {
                     log::error!("Lavin MQ loop error: {e:?}");
                 }
             });
         });
         self.mq_thread_handle = Some(handle);
+        self.mq_thread_done = Some(mq_done_rx);
 
         log::info!("geyser plugin loaded ok ()");
         Ok(())
     }
 
     fn on_unload(&mut self) {
-        self.quic_server = None;
+        log::info!("unloading quic_geyser plugin");
+        self.filters_reload_stop = None;
+        join_thread("filters reload", self.filters_reload_handle.take());
+        // geyser callbacks ignore events once the quic server is gone
+        let quic_server = self.quic_server.take();
+        self.rpc_server_message_channel = None;
+
+        // the block builder stops once its channel is closed, then the block forwarder
+        self.block_builder_channel = None;
+        join_thread("block builder", self.block_builder_handle.take());
+        join_thread("MQ block forwarder", self.mq_block_forwarder_handle.take());
+
+        // the MQ loop publishes what is left within its shutdown timeout and closes the connection
+        self.mq_sender = None;
+        if let (Some(handle), Some(done)) =
+            (self.mq_thread_handle.take(), self.mq_thread_done.take())
+        {
+            match done.recv_timeout(self.mq_shutdown_timeout + MQ_CLOSE_GRACE) {
+                Err(RecvTimeoutError::Timeout) => {
+                    log::error!("MQ loop did not stop in time, leaving it behind")
+                }
+                _ => join_thread("MQ", Some(handle)),
+            }
+        }
+
+        if let Some(quic_server) = quic_server {
+            quic_server.shutdown();
+        }
+        log::info!("quic_geyser plugin unloaded");
     }
 
     fn update_account(
@@ -143,12 +245,6 @@ impl GeyserPlugin for QuicGeyserPlugin {
         };
         let pubkey: Pubkey = Pubkey::try_from(account_info.pubkey).expect("valid pubkey");
 
-        // Use the allowed pubkeys loaded from the config.
-        let owner = Pubkey::try_from(account_info.owner).expect("valid pubkey");
-        if !self.account_update_pubkeys.contains(&owner) {
-            return Ok(());
-        }
-
         let channel_message = ChannelMessage::Account(
             AccountData {
                 pubkey,
@@ -158,25 +254,8 @@ impl GeyserPlugin for QuicGeyserPlugin {
             slot,
             is_startup,
         );
-
-        if let Some(mq_tx) = &self.mq_sender {
-            if let Err(send_err) = mq_tx.send(channel_message.clone()) {
-                log::error!("Failed to send account update to MQ server: {send_err}");
-            }
-        }
-
-        if let Some(block_channel) = &self.block_builder_channel {
-            let _ = block_channel.send(channel_message.clone());
-        }
-
-        if let Some(rpc_server_message_channel) = &self.rpc_server_message_channel {
-            let _ = rpc_server_message_channel.send(channel_message.clone());
-        }
-
-        quic_server
-            .send_message(channel_message)
-            .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
-        Ok(())
+        let outputs = self.filters.load().account_outputs(&channel_message);
+        self.send_to_outputs(channel_message, outputs, "account update")
     }
 
     fn notify_end_of_startup(&self) -> PluginResult<()> {
@@ -203,6 +282,12 @@ impl GeyserPlugin for QuicGeyserPlugin {
             let _ = block_channel.send(slot_message.clone());
         }
 
+        if let Some(mq_tx) = &self.mq_sender {
+            if let Err(send_err) = mq_tx.send(slot_message.clone()) {
+                log::error!("Failed to send slot update to MQ server: {send_err}");
+            }
+        }
+
         if let Some(rpc_server_message_channel) = &self.rpc_server_message_channel {
             let _ = rpc_server_message_channel.send(slot_message.clone());
         }
@@ -218,9 +303,9 @@ impl GeyserPlugin for QuicGeyserPlugin {
         transaction: ReplicaTransactionInfoVersions,
         slot: Slot,
     ) -> PluginResult<()> {
-        let Some(quic_server) = &self.quic_server else {
+        if self.quic_server.is_none() {
             return Ok(());
-        };
+        }
 
         let ReplicaTransactionInfoVersions::V0_0_2(solana_transaction) = transaction else {
             return Err(GeyserPluginError::TransactionUpdateError {
@@ -229,32 +314,32 @@ impl GeyserPlugin for QuicGeyserPlugin {
         };
 
         let message = solana_transaction.transaction.message();
-        let mut account_keys = vec![];
-        for index in 0.. {
-            match message.account_keys().get(index) {
-                Some(account) => account_keys.push(*account),
-                None => break,
-            }
-        }
-
-        // Check if any of the allowed transaction pubkeys are present.
-        if !self
-            .transaction_pubkeys
-            .iter()
-            .any(|key| account_keys.contains(key))
-        {
+        let status_meta = solana_transaction.transaction_status_meta;
+        // static keys followed by the addresses loaded from lookup tables
+        let accounts = TransactionAccounts {
+            header: message.header(),
+            static_account_keys: message.static_account_keys(),
+            loaded_addresses: &status_meta.loaded_addresses,
+        };
+        let outputs = self.filters.load().transaction_outputs(
+            &accounts,
+            solana_transaction.is_vote,
+            status_meta.status.is_err(),
+        );
+        // checked before the transaction meta is converted
+        if !outputs.any() {
             return Ok(());
         }
 
+        // static keys followed by the loaded addresses, so account indexes stay valid
         let v0_message = Message {
             header: *message.header(),
-            account_keys,
+            account_keys: message.account_keys().iter().copied().collect(),
             recent_blockhash: *message.recent_blockhash(),
             instructions: message.instructions().to_vec(),
             address_table_lookups: message.message_address_table_lookups().to_vec(),
         };
 
-        let status_meta = solana_transaction.transaction_status_meta;
         let transaction = Transaction {
             slot_identifier: SlotIdentifier { slot },
             signatures: solana_transaction.transaction.signatures().to_vec(),
@@ -316,34 +401,39 @@ impl GeyserPlugin for QuicGeyserPlugin {
             index: solana_transaction.index as u64,
         };
 
-        if transaction.transaction_meta.error.is_some() {
-            log::info!(
-                "Skipping transaction with error: {:?}",
-                transaction.transaction_meta.error
-            );
-            return Ok(());
-        }
-
         let transaction_message = ChannelMessage::Transaction(Box::new(transaction));
-
-        if let Some(block_channel) = &self.block_builder_channel {
-            let _ = block_channel.send(transaction_message.clone());
-        }
-
-        if let Some(mq_tx) = &self.mq_sender {
-            if let Err(send_err) = mq_tx.send(transaction_message.clone()) {
-                log::error!("Failed to send transaction to MQ server: {send_err}");
-            }
-        }
-
-        quic_server
-            .send_message(transaction_message)
-            .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
-        Ok(())
+        self.send_to_outputs(transaction_message, outputs, "transaction")
     }
 
-    fn notify_entry(&self, _entry: ReplicaEntryInfoVersions) -> PluginResult<()> {
-        Ok(())
+    fn notify_entry(&self, entry: ReplicaEntryInfoVersions) -> PluginResult<()> {
+        if self.quic_server.is_none() {
+            return Ok(());
+        }
+        let entry = match entry {
+            ReplicaEntryInfoVersions::V0_0_1(info) => Entry {
+                slot: info.slot,
+                index: info.index as u64,
+                num_hashes: info.num_hashes,
+                hash: bs58::encode(info.hash).into_string(),
+                executed_transaction_count: info.executed_transaction_count,
+                starting_transaction_index: 0,
+            },
+            ReplicaEntryInfoVersions::V0_0_2(info) => Entry {
+                slot: info.slot,
+                index: info.index as u64,
+                num_hashes: info.num_hashes,
+                hash: bs58::encode(info.hash).into_string(),
+                executed_transaction_count: info.executed_transaction_count,
+                starting_transaction_index: info.starting_transaction_index as u64,
+            },
+        };
+        // entries are not filtered, the block builder needs all of them to complete its blocks
+        let outputs = Outputs {
+            quic: true,
+            mq: true,
+            block_builder: true,
+        };
+        self.send_to_outputs(ChannelMessage::Entry(entry), outputs, "entry")
     }
 
     fn notify_block_metadata(&self, blockinfo: ReplicaBlockInfoVersions) -> PluginResult<()> {
@@ -439,7 +529,51 @@ impl GeyserPlugin for QuicGeyserPlugin {
     }
 
     fn entry_notifications_enabled(&self) -> bool {
-        false
+        self.entry_notifications
+    }
+}
+
+impl QuicGeyserPlugin {
+    /// Sends an account update, a transaction or an entry to the outputs whose filters allow it.
+    fn send_to_outputs(
+        &self,
+        message: ChannelMessage,
+        outputs: Outputs,
+        kind: &str,
+    ) -> PluginResult<()> {
+        if outputs.mq {
+            if let Some(mq_tx) = &self.mq_sender {
+                if let Err(send_err) = mq_tx.send(message.clone()) {
+                    log::error!("Failed to send {kind} to MQ server: {send_err}");
+                }
+            }
+        }
+
+        if outputs.block_builder {
+            if let Some(block_channel) = &self.block_builder_channel {
+                let _ = block_channel.send(message.clone());
+            }
+        }
+
+        if outputs.quic {
+            if let Some(rpc_server_message_channel) = &self.rpc_server_message_channel {
+                let _ = rpc_server_message_channel.send(message.clone());
+            }
+            if let Some(quic_server) = &self.quic_server {
+                quic_server
+                    .send_message(message)
+                    .map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn join_thread(name: &str, handle: Option<JoinHandle<()>>) {
+    if let Some(handle) = handle {
+        if handle.join().is_err() {
+            log::error!("{name} thread panicked");
+        }
     }
 }
 
@@ -0,0 +1,131 @@
+use quic_geyser_common::{channel_message::AccountData, types::transaction::Transaction};
+use solana_sdk::{clock::Slot, commitment_config::CommitmentConfig};
+
+use crate::config::{ConfigQueue, ExchangeType};
+
+const PROGRAM_ID_PLACEHOLDER: &str = "{program_id}";
+
+/// Routing keys of a transaction, `{status}` is `success` or `failed`.
+/// With a `{program_id}` placeholder the transaction fans out under every program it invokes.
+pub fn transaction_routing_keys(template: &str, transaction: &Transaction) -> Vec<String> {
+    let signature = transaction
+        .signatures
+        .first()
+        .map(|signature| signature.to_string())
+        .unwrap_or_default();
+    let routing_key = render_routing_key(
+        template,
+        &[
+            ("slot", transaction.slot_identifier.slot.to_string()),
+            ("signature", signature),
+            ("status", transaction_status(transaction).to_string()),
+        ],
+    );
+    if !routing_key.contains(PROGRAM_ID_PLACEHOLDER) {
+        return vec![routing_key];
+    }
+
+    let program_ids = transaction.program_ids();
+    if program_ids.is_empty() {
+        return vec![routing_key.replace(PROGRAM_ID_PLACEHOLDER, "")];
+    }
+    program_ids
+        .iter()
+        .map(|program_id| routing_key.replace(PROGRAM_ID_PLACEHOLDER, &program_id.to_string()))
+        .collect()
+}
+
+fn transaction_status(transaction: &Transaction) -> &'static str {
+    if transaction.transaction_meta.error.is_some() {
+        "failed"
+    } else {
+        "success"
+    }
+}
+
+pub fn account_routing_key(template: &str, account_data: &AccountData, slot: Slot) -> String {
+    render_routing_key(
+        template,
+        &[
+            ("slot", slot.to_string()),
+            ("pubkey", account_data.pubkey.to_string()),
+            ("owner", account_data.account.owner.to_string()),
+        ],
+    )
+}
+
+pub fn block_meta_routing_key(template: &str, slot: Slot) -> String {
+    render_routing_key(template, &[("slot", slot.to_string())])
+}
+
+pub fn slot_routing_key(template: &str, slot: Slot, commitment: CommitmentConfig) -> String {
+    render_routing_key(
+        template,
+        &[
+            ("slot", slot.to_string()),
+            ("commitment", commitment.commitment.to_string()),
+        ],
+    )
+}
+
+pub fn block_routing_key(template: &str, slot: Slot) -> String {
+    render_routing_key(template, &[("slot", slot.to_string())])
+}
+
+pub fn entry_routing_key(template: &str, slot: Slot) -> String {
+    render_routing_key(template, &[("slot", slot.to_string())])
+}
+
+/// Replaces `{name}` placeholders of the template with their values.
+fn render_routing_key(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |routing_key, (name, value)| {
+            routing_key.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Names of the declared queues the broker delivers a message published with `routing_key` to.
+pub fn routed_queues<'a>(
+    exchange: &str,
+    exchange_type: ExchangeType,
+    queues: &'a [ConfigQueue],
+    routing_key: &str,
+) -> Vec<&'a str> {
+    queues
+        .iter()
+        .filter(|queue| {
+            // the default exchange routes by queue name and ignores bindings
+            if exchange.is_empty() {
+                return queue.name == routing_key;
+            }
+            let matches = |binding: &str| match exchange_type {
+                ExchangeType::Direct => binding == routing_key,
+                ExchangeType::Topic => topic_matches(binding, routing_key),
+                ExchangeType::Fanout => true,
+            };
+            if queue.bindings.is_empty() {
+                matches(&queue.name)
+            } else {
+                queue.bindings.iter().any(|binding| matches(binding))
+            }
+        })
+        .map(|queue| queue.name.as_str())
+        .collect()
+}
+
+/// AMQP topic matching, `*` matches one word and `#` zero or more words.
+pub fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], words: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => words.is_empty(),
+            Some((&"#", rest)) => (0..=words.len()).any(|skipped| matches(rest, &words[skipped..])),
+            Some((&word, rest)) => words.split_first().is_some_and(|(first, other_words)| {
+                (word == "*" || word == *first) && matches(rest, other_words)
+            }),
+        }
+    }
+    let pattern = pattern.split('.').collect::<Vec<_>>();
+    let words = routing_key.split('.').collect::<Vec<_>>();
+    matches(&pattern, &words)
+}
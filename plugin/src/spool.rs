@@ -0,0 +1,556 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigSpool;
+
+const SEGMENT_EXTENSION: &str = "seg";
+const ACK_CURSOR_FILE: &str = "ack_cursor";
+const RECORD_HEADER_SIZE: u64 = 8;
+// persisting the cursor on every confirm is too costly, after a crash we replay at most these many
+// already confirmed entries
+const ACKS_BETWEEN_CURSOR_SYNC: u64 = 256;
+
+/// Position just after a record in the spool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SpoolPosition {
+    pub segment: u64,
+    pub offset: u64,
+}
+
+/// A message ready to be published to the broker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpoolEntry {
+    pub routing_key: String,
+    /// Published to the dead-letter exchange instead of the main one.
+    pub dead_letter: bool,
+    pub properties: MessageProperties,
+    pub payload: Vec<u8>,
+}
+
+/// AMQP properties of a spooled message, fixed when the plugin receives it so replays are identical.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageProperties {
+    pub content_type: String,
+    /// Set when the payload is compressed, see `payload::compress`.
+    pub content_encoding: Option<String>,
+    /// Deterministic id consumers can deduplicate on: the first signature of transactions,
+    /// `<pubkey>:<write_version>` of account updates, the blockhash of block metas and blocks,
+    /// `<slot>:<commitment>` of slot updates and `<slot>:<index>` of entries.
+    pub message_id: String,
+    /// Unix time in seconds at which the plugin received the message.
+    pub timestamp: u64,
+    pub headers: BTreeMap<String, HeaderValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeaderValue {
+    Integer(i64),
+    String(String),
+    Strings(Vec<String>),
+}
+
+/// Write-ahead spool between the plugin and the AMQP broker.
+///
+/// Entries are appended to segment files `<id>.seg` as `[u64 length][bincode entry]` records.
+/// The ack cursor marks everything the broker has confirmed, segments behind it are deleted.
+/// On open the spool resumes from the ack cursor, so unconfirmed entries are replayed in order.
+/// Appended entries survive a crash of the plugin right away and a crash of the host once they
+/// are synced to disk, at most `sync_interval_ms` later (see `sync_if_due`).
+///
+/// Without a directory the segments are kept in memory: entries are still replayed after a
+/// reconnect and dropped only once confirmed, but they are lost when the plugin stops.
+pub struct Spool {
+    storage: Storage,
+    segment_size: u64,
+    max_size: u64,
+    total_size: u64,
+    // segment id -> size in bytes, the last segment is the one being written
+    segments: BTreeMap<u64, u64>,
+    read_position: SpoolPosition,
+    acked_position: SpoolPosition,
+    unsynced_acks: u64,
+    sync_interval: Duration,
+    last_sync: Instant,
+    // entries were appended since the last sync of the segment being written
+    unsynced_writes: bool,
+}
+
+// where the segments are kept
+enum Storage {
+    Disk {
+        directory: PathBuf,
+        writer: BufWriter<File>,
+        reader: Option<(u64, BufReader<File>)>,
+    },
+    // segment id -> records
+    Memory(BTreeMap<u64, Vec<u8>>),
+}
+
+impl Spool {
+    pub fn open(config: &ConfigSpool) -> anyhow::Result<Self> {
+        let Some(directory) = config.directory.clone() else {
+            log::warn!("MQ spool has no directory, unconfirmed messages are lost on unload");
+            return Ok(Self::new(
+                Storage::Memory(BTreeMap::from([(0, vec![])])),
+                BTreeMap::from([(0, 0)]),
+                SpoolPosition::default(),
+                config,
+            ));
+        };
+        // the working directory of the validator is not a place to keep messages
+        anyhow::ensure!(
+            directory.is_absolute(),
+            "MQ spool directory {} is not an absolute path",
+            directory.display()
+        );
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("creating MQ spool directory {}", directory.display()))?;
+
+        let mut segments = BTreeMap::new();
+        for dir_entry in fs::read_dir(&directory)? {
+            let path = dir_entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            else {
+                log::warn!("Ignoring unknown file in MQ spool: {}", path.display());
+                continue;
+            };
+            segments.insert(id, fs::metadata(&path)?.len());
+        }
+
+        let mut acked_position = read_ack_cursor(&directory)?.unwrap_or_default();
+
+        // segments before the cursor were confirmed but not deleted before a crash
+        let confirmed = segments
+            .range(..acked_position.segment)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in confirmed {
+            fs::remove_file(segment_path(&directory, id))?;
+            segments.remove(&id);
+        }
+
+        match segments.first_key_value() {
+            Some((&first, _)) if first > acked_position.segment => {
+                acked_position = SpoolPosition {
+                    segment: first,
+                    offset: 0,
+                };
+            }
+            Some(_) => {}
+            None => {
+                File::create(segment_path(&directory, acked_position.segment))?;
+                segments.insert(acked_position.segment, 0);
+                acked_position.offset = 0;
+            }
+        }
+
+        // a crash during an append leaves a torn record at the end of the last segment
+        let (&last, &last_size) = segments.last_key_value().expect("spool has a segment");
+        let last_path = segment_path(&directory, last);
+        let valid_size = valid_segment_length(&last_path, last_size)?;
+        if valid_size != last_size {
+            log::warn!(
+                "Truncating torn record at the end of MQ spool segment {} ({} -> {} bytes)",
+                last,
+                last_size,
+                valid_size
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(&last_path)?
+                .set_len(valid_size)?;
+            segments.insert(last, valid_size);
+        }
+
+        let acked_segment_size = segments[&acked_position.segment];
+        acked_position.offset = acked_position.offset.min(acked_segment_size);
+
+        let writer = BufWriter::new(OpenOptions::new().append(true).open(&last_path)?);
+        let storage = Storage::Disk {
+            directory,
+            writer,
+            reader: None,
+        };
+
+        let mut spool = Self::new(storage, segments, acked_position, config);
+        spool.sync_cursor()?;
+        if spool.has_unread() {
+            log::info!(
+                "MQ spool opened with {} bytes of unconfirmed messages to replay",
+                spool.size()
+            );
+        }
+        Ok(spool)
+    }
+
+    fn new(
+        storage: Storage,
+        segments: BTreeMap<u64, u64>,
+        acked_position: SpoolPosition,
+        config: &ConfigSpool,
+    ) -> Self {
+        Self {
+            storage,
+            segment_size: config.segment_size,
+            max_size: config.max_size,
+            total_size: segments.values().sum(),
+            segments,
+            read_position: acked_position,
+            acked_position,
+            unsynced_acks: 0,
+            sync_interval: Duration::from_millis(config.sync_interval_ms),
+            last_sync: Instant::now(),
+            unsynced_writes: false,
+        }
+    }
+
+    /// Appends an entry, returns false if the spool is full.
+    pub fn append(&mut self, entry: &SpoolEntry) -> anyhow::Result<bool> {
+        Ok(self.append_at(entry)?.is_some())
+    }
+
+    /// Appends an entry handed out before again, so the spool can be acked past its first copy.
+    /// Returns the position of the new copy, or None if the spool is full.
+    pub fn requeue(&mut self, entry: &SpoolEntry) -> anyhow::Result<Option<SpoolPosition>> {
+        self.append_at(entry)
+    }
+
+    fn append_at(&mut self, entry: &SpoolEntry) -> anyhow::Result<Option<SpoolPosition>> {
+        let binary = bincode::serialize(entry)?;
+        let record_size = RECORD_HEADER_SIZE + binary.len() as u64;
+        if self.size() + record_size > self.max_size {
+            return Ok(None);
+        }
+
+        let (&last, &last_size) = self.segments.last_key_value().expect("spool has a segment");
+        if last_size > 0 && last_size + record_size > self.segment_size {
+            self.roll_segment(last + 1)?;
+        }
+
+        match &mut self.storage {
+            Storage::Disk { writer, .. } => {
+                writer.write_all(&(binary.len() as u64).to_le_bytes())?;
+                writer.write_all(&binary)?;
+                // readers use their own file handle, so the record has to reach the file
+                writer.flush()?;
+            }
+            Storage::Memory(segments) => {
+                let mut segment = segments.last_entry().expect("spool has a segment");
+                segment
+                    .get_mut()
+                    .extend_from_slice(&(binary.len() as u64).to_le_bytes());
+                segment.get_mut().extend_from_slice(&binary);
+            }
+        }
+
+        self.unsynced_writes = true;
+
+        let mut last = self.segments.last_entry().expect("spool has a segment");
+        *last.get_mut() += record_size;
+        let position = SpoolPosition {
+            segment: *last.key(),
+            offset: *last.get(),
+        };
+        self.total_size += record_size;
+        Ok(Some(position))
+    }
+
+    /// Reads the next entry which has not been handed out yet, with the position to ack it.
+    // reading can fail, so the spool is not an iterator
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> anyhow::Result<Option<(SpoolPosition, SpoolEntry)>> {
+        loop {
+            match self.segments.get(&self.read_position.segment) {
+                Some(&size) if self.read_position.offset < size => {}
+                _ => {
+                    let Some((&next, _)) =
+                        self.segments.range(self.read_position.segment + 1..).next()
+                    else {
+                        return Ok(None);
+                    };
+                    self.read_position = SpoolPosition {
+                        segment: next,
+                        offset: 0,
+                    };
+                    self.storage.close_reader();
+                    continue;
+                }
+            }
+
+            let position = self.read_position;
+            let max_length = self.segments[&position.segment]
+                .saturating_sub(position.offset + RECORD_HEADER_SIZE);
+            let record = self.storage.read_record(position, max_length);
+            let binary = match record {
+                Ok(binary) => binary,
+                Err(e) => {
+                    // cannot find the next record boundary, give up on the rest of the segment
+                    log::error!(
+                        "Failed to read MQ spool segment {} at offset {}: {e}, skipping the rest of the segment",
+                        position.segment,
+                        position.offset
+                    );
+                    self.read_position.offset = self.segments[&position.segment];
+                    self.storage.close_reader();
+                    continue;
+                }
+            };
+            self.read_position.offset += RECORD_HEADER_SIZE + binary.len() as u64;
+
+            match bincode::deserialize::<SpoolEntry>(&binary) {
+                Ok(entry) => return Ok(Some((self.read_position, entry))),
+                Err(e) => {
+                    log::error!(
+                        "Skipping corrupted MQ spool record in segment {} at offset {}: {e}",
+                        position.segment,
+                        position.offset
+                    );
+                }
+            }
+        }
+    }
+
+    /// Marks everything up to `position` as confirmed by the broker.
+    pub fn ack(&mut self, position: SpoolPosition) -> anyhow::Result<()> {
+        if position <= self.acked_position {
+            return Ok(());
+        }
+        self.acked_position = position;
+
+        // move the cursor past fully confirmed segments, the segment being written is kept
+        let last = *self
+            .segments
+            .keys()
+            .next_back()
+            .expect("spool has a segment");
+        while self.acked_position.segment != last {
+            let size = self
+                .segments
+                .get(&self.acked_position.segment)
+                .copied()
+                .unwrap_or_default();
+            if self.acked_position.offset < size {
+                break;
+            }
+            let next = *self
+                .segments
+                .range(self.acked_position.segment + 1..)
+                .next()
+                .expect("last segment is after the cursor")
+                .0;
+            self.acked_position = SpoolPosition {
+                segment: next,
+                offset: 0,
+            };
+        }
+
+        let confirmed = self
+            .segments
+            .range(..self.acked_position.segment)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        let removed_segments = !confirmed.is_empty();
+        for id in confirmed {
+            if let Some(size) = self.segments.remove(&id) {
+                self.total_size -= size;
+            }
+            self.storage.remove_segment(id)?;
+        }
+
+        self.unsynced_acks += 1;
+        if removed_segments || self.unsynced_acks >= ACKS_BETWEEN_CURSOR_SYNC {
+            self.sync_cursor()?;
+        }
+        Ok(())
+    }
+
+    /// Hands out every unconfirmed entry again, used after reconnecting to the broker.
+    pub fn rewind(&mut self) {
+        self.read_position = self.acked_position;
+        self.storage.close_reader();
+    }
+
+    pub fn has_unread(&self) -> bool {
+        let (&last, &size) = self.segments.last_key_value().expect("spool has a segment");
+        self.read_position
+            < SpoolPosition {
+                segment: last,
+                offset: size,
+            }
+    }
+
+    /// Bytes of entries not confirmed yet, this is what the size cap applies to.
+    pub fn size(&self) -> u64 {
+        self.total_size - self.acked_position.offset
+    }
+
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        self.sync()?;
+        self.sync_cursor()
+    }
+
+    /// Syncs the appended entries to disk if `sync_interval_ms` passed since the last sync.
+    pub fn sync_if_due(&mut self) -> anyhow::Result<()> {
+        if self.unsynced_writes && self.last_sync.elapsed() >= self.sync_interval {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Syncs the appended entries to disk, so they survive a crash of the host.
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        if let Storage::Disk { writer, .. } = &mut self.storage {
+            writer.flush()?;
+            if self.unsynced_writes {
+                writer.get_ref().sync_data()?;
+            }
+        }
+        self.unsynced_writes = false;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    fn roll_segment(&mut self, id: u64) -> anyhow::Result<()> {
+        // the segment is not written anymore, it is synced now or never
+        self.sync()?;
+        match &mut self.storage {
+            Storage::Disk {
+                directory, writer, ..
+            } => *writer = BufWriter::new(File::create(segment_path(directory, id))?),
+            Storage::Memory(segments) => {
+                segments.insert(id, vec![]);
+            }
+        }
+        self.segments.insert(id, 0);
+        Ok(())
+    }
+
+    fn sync_cursor(&mut self) -> anyhow::Result<()> {
+        if let Storage::Disk { directory, .. } = &self.storage {
+            let mut bytes = self.acked_position.segment.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&self.acked_position.offset.to_le_bytes());
+            let tmp_path = directory.join(format!("{ACK_CURSOR_FILE}.tmp"));
+            fs::write(&tmp_path, bytes)?;
+            fs::rename(tmp_path, directory.join(ACK_CURSOR_FILE))?;
+        }
+        self.unsynced_acks = 0;
+        Ok(())
+    }
+}
+
+impl Storage {
+    // record starting at `position`, `max_length` is what is left of the segment after its header
+    fn read_record(&mut self, position: SpoolPosition, max_length: u64) -> io::Result<Vec<u8>> {
+        // a corrupted length is not trusted with an allocation
+        let check_length = |header: [u8; RECORD_HEADER_SIZE as usize]| {
+            let length = u64::from_le_bytes(header);
+            if length > max_length {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("record length {length} goes past the end of the segment"),
+                ));
+            }
+            Ok(length as usize)
+        };
+        match self {
+            Storage::Disk {
+                directory, reader, ..
+            } => {
+                let reopen = !matches!(reader, Some((segment, _)) if *segment == position.segment);
+                if reopen {
+                    let mut file = File::open(segment_path(directory, position.segment))?;
+                    file.seek(SeekFrom::Start(position.offset))?;
+                    *reader = Some((position.segment, BufReader::new(file)));
+                }
+                let reader = &mut reader.as_mut().expect("reader is open").1;
+                let mut header = [0; RECORD_HEADER_SIZE as usize];
+                reader.read_exact(&mut header)?;
+                let mut binary = vec![0; check_length(header)?];
+                reader.read_exact(&mut binary)?;
+                Ok(binary)
+            }
+            Storage::Memory(segments) => {
+                let mut record = &segments[&position.segment][position.offset as usize..];
+                let mut header = [0; RECORD_HEADER_SIZE as usize];
+                record.read_exact(&mut header)?;
+                let length = check_length(header)?;
+                Ok(record[..length].to_vec())
+            }
+        }
+    }
+
+    fn close_reader(&mut self) {
+        if let Storage::Disk { reader, .. } = self {
+            *reader = None;
+        }
+    }
+
+    fn remove_segment(&mut self, id: u64) -> anyhow::Result<()> {
+        match self {
+            Storage::Disk { directory, .. } => fs::remove_file(segment_path(directory, id))?,
+            Storage::Memory(segments) => {
+                segments.remove(&id);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("Failed to flush MQ spool: {e}");
+        }
+    }
+}
+
+fn segment_path(directory: &Path, id: u64) -> PathBuf {
+    directory.join(format!("{id:020}.{SEGMENT_EXTENSION}"))
+}
+
+fn read_ack_cursor(directory: &Path) -> anyhow::Result<Option<SpoolPosition>> {
+    let bytes = match fs::read(directory.join(ACK_CURSOR_FILE)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if bytes.len() != 16 {
+        log::error!("MQ spool ack cursor is corrupted, replaying the whole spool");
+        return Ok(None);
+    }
+    Ok(Some(SpoolPosition {
+        segment: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+        offset: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+    }))
+}
+
+// length of the segment up to the last complete record
+fn valid_segment_length(path: &Path, file_size: u64) -> anyhow::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut offset = 0;
+    let mut header = [0; RECORD_HEADER_SIZE as usize];
+    while offset + RECORD_HEADER_SIZE <= file_size {
+        reader.read_exact(&mut header)?;
+        let length = u64::from_le_bytes(header);
+        if offset + RECORD_HEADER_SIZE + length > file_size {
+            break;
+        }
+        reader.seek_relative(length as i64)?;
+        offset += RECORD_HEADER_SIZE + length;
+    }
+    Ok(offset)
+}
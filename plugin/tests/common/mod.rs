@@ -0,0 +1,61 @@
+//! Messages shared by the integration tests.
+
+// every test crate uses only some of them
+#![allow(dead_code)]
+
+use quic_geyser_common::channel_message::{AccountData, ChannelMessage};
+use solana_sdk::{
+    account::Account, clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey,
+};
+
+/// Update of a new account in `slot`, with the slot as write version.
+pub fn account(slot: Slot) -> ChannelMessage {
+    account_update(Pubkey::new_unique(), Account::default(), slot, slot)
+}
+
+/// Write `write_version` of `pubkey` in `slot`, the lamports tell the writes apart.
+pub fn account_write(pubkey: Pubkey, slot: Slot, write_version: u64) -> ChannelMessage {
+    let account = Account {
+        lamports: write_version,
+        ..Account::default()
+    };
+    account_update(pubkey, account, slot, write_version)
+}
+
+/// Update of `pubkey` owned by `owner` with `data`, in slot 10.
+pub fn owned_account(pubkey: Pubkey, owner: Pubkey, data: Vec<u8>) -> ChannelMessage {
+    let account = Account {
+        lamports: 1,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    };
+    account_update(pubkey, account, 10, 1)
+}
+
+pub fn account_update(
+    pubkey: Pubkey,
+    account: Account,
+    slot: Slot,
+    write_version: u64,
+) -> ChannelMessage {
+    ChannelMessage::Account(
+        AccountData {
+            pubkey,
+            account,
+            write_version,
+        },
+        slot,
+        false,
+    )
+}
+
+/// Processed update of `slot`, child of the slot before it.
+pub fn slot(slot: Slot) -> ChannelMessage {
+    slot_at(slot, slot - 1, CommitmentConfig::processed())
+}
+
+pub fn slot_at(slot: Slot, parent: Slot, commitment: CommitmentConfig) -> ChannelMessage {
+    ChannelMessage::Slot(slot, parent, commitment)
+}
@@ -0,0 +1,62 @@
+use quic_geyser_plugin::{account_coalescer::AccountCoalescer, config::ConfigCoalesce};
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    pubkey::Pubkey,
+};
+
+mod common;
+
+use common::{account_write, slot_at};
+
+#[test]
+fn test_last_write_of_a_slot_is_released_when_the_slot_advances() {
+    let mut coalescer = AccountCoalescer::new(&ConfigCoalesce::default());
+    let (pool, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+    assert!(coalescer.push(account_write(pool, 10, 1)).is_empty());
+    assert!(coalescer.push(account_write(other, 10, 2)).is_empty());
+    assert!(coalescer.push(account_write(pool, 10, 4)).is_empty());
+    // writes can be notified out of order
+    assert!(coalescer.push(account_write(pool, 10, 3)).is_empty());
+    assert_eq!(coalescer.len(), 2);
+
+    let slot_11 = slot_at(11, 10, CommitmentConfig::processed());
+    assert_eq!(
+        coalescer.push(slot_11.clone()),
+        vec![
+            account_write(other, 10, 2),
+            account_write(pool, 10, 4),
+            slot_11
+        ]
+    );
+    assert!(coalescer.is_empty());
+
+    // late writes of a released slot are not held
+    let late = account_write(pool, 10, 5);
+    assert_eq!(coalescer.push(late.clone()), vec![late]);
+}
+
+#[test]
+fn test_slot_is_released_at_the_flush_commitment() {
+    let mut coalescer = AccountCoalescer::new(&ConfigCoalesce {
+        flush_commitment: Some(CommitmentLevel::Confirmed),
+    });
+    let pool = Pubkey::new_unique();
+
+    assert!(coalescer.push(account_write(pool, 10, 1)).is_empty());
+    assert!(coalescer.push(account_write(pool, 10, 2)).is_empty());
+    assert!(coalescer.push(account_write(pool, 11, 3)).is_empty());
+
+    let processed_11 = slot_at(11, 10, CommitmentConfig::processed());
+    assert_eq!(coalescer.push(processed_11.clone()), vec![processed_11]);
+
+    let confirmed_10 = slot_at(10, 9, CommitmentConfig::confirmed());
+    assert_eq!(
+        coalescer.push(confirmed_10.clone()),
+        vec![account_write(pool, 10, 2), confirmed_10]
+    );
+    assert_eq!(coalescer.len(), 1);
+
+    assert_eq!(coalescer.flush(), vec![account_write(pool, 11, 3)]);
+    assert!(coalescer.is_empty());
+}
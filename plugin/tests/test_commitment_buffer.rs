@@ -0,0 +1,75 @@
+use quic_geyser_plugin::commitment_buffer::CommitmentBuffer;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+
+mod common;
+
+use common::{account, slot_at};
+
+#[test]
+fn test_processed_commitment_is_not_buffered() {
+    let mut buffer = CommitmentBuffer::new(CommitmentLevel::Processed);
+    let message = account(10);
+    assert_eq!(buffer.push(message.clone()), vec![message]);
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_confirmed_slot_releases_its_ancestors() {
+    let mut buffer = CommitmentBuffer::new(CommitmentLevel::Confirmed);
+    let processed_10 = slot_at(10, 9, CommitmentConfig::processed());
+    let processed_11 = slot_at(11, 10, CommitmentConfig::processed());
+    let (account_10, account_11) = (account(10), account(11));
+
+    assert_eq!(buffer.push(processed_10.clone()), vec![processed_10]);
+    assert!(buffer.push(account_10.clone()).is_empty());
+    assert_eq!(buffer.push(processed_11.clone()), vec![processed_11]);
+    assert!(buffer.push(account_11.clone()).is_empty());
+    assert_eq!(buffer.len(), 2);
+
+    // slot 10 never gets its own confirmed update
+    let confirmed_11 = slot_at(11, 10, CommitmentConfig::confirmed());
+    assert_eq!(
+        buffer.push(confirmed_11.clone()),
+        vec![account_10, account_11, confirmed_11]
+    );
+    assert!(buffer.is_empty());
+
+    // late events of a released slot go out directly
+    let late = account(11);
+    assert_eq!(buffer.push(late.clone()), vec![late]);
+}
+
+#[test]
+fn test_dead_fork_is_discarded_on_root() {
+    let mut buffer = CommitmentBuffer::new(CommitmentLevel::Finalized);
+    buffer.push(slot_at(10, 9, CommitmentConfig::processed()));
+    buffer.push(slot_at(11, 10, CommitmentConfig::processed()));
+    buffer.push(slot_at(12, 10, CommitmentConfig::processed()));
+    let account_11 = account(11);
+    assert!(buffer.push(account_11.clone()).is_empty());
+    assert!(buffer.push(account(12)).is_empty());
+
+    // confirmed is not enough
+    buffer.push(slot_at(11, 10, CommitmentConfig::confirmed()));
+    assert_eq!(buffer.len(), 2);
+
+    let rooted_13 = slot_at(13, 11, CommitmentConfig::finalized());
+    buffer.push(slot_at(13, 11, CommitmentConfig::processed()));
+    assert_eq!(buffer.push(rooted_13.clone()), vec![account_11, rooted_13]);
+    assert!(buffer.is_empty());
+
+    // slot 12 was on the dead fork
+    assert!(buffer.push(account(12)).is_empty());
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_flush_hands_out_held_events() {
+    let mut buffer = CommitmentBuffer::new(CommitmentLevel::Confirmed);
+    let (account_11, account_10) = (account(11), account(10));
+    assert!(buffer.push(account_11.clone()).is_empty());
+    assert!(buffer.push(account_10.clone()).is_empty());
+
+    assert_eq!(buffer.flush(), vec![account_10, account_11]);
+    assert!(buffer.is_empty());
+}
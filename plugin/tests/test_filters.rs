@@ -0,0 +1,295 @@
+use quic_geyser_common::filters::TransactionAccounts;
+use quic_geyser_plugin::{
+    config::Config,
+    filters::{FilterScope, Outputs, PluginFilters},
+};
+use solana_sdk::{
+    bs58,
+    message::{v0::LoadedAddresses, MessageHeader},
+    pubkey::Pubkey,
+};
+
+mod common;
+
+use common::owned_account;
+
+fn plugin_filters(config: serde_json::Value) -> anyhow::Result<PluginFilters> {
+    let mut config_json = serde_json::json!({
+        "libpath": "libquic_geyser_plugin.so",
+        "quic_plugin": {},
+    });
+    for (key, value) in config.as_object().unwrap() {
+        config_json[key] = value.clone();
+    }
+    let config: Config = serde_json::from_value(config_json)?;
+    PluginFilters::new(&config)
+}
+
+/// Filters of the MQ output, at the top level of the config.
+fn filters(config: serde_json::Value) -> anyhow::Result<FilterScope> {
+    Ok(plugin_filters(config)?.mq.unwrap())
+}
+
+// one writable signer, then writable accounts and a readonly last one
+const HEADER: MessageHeader = MessageHeader {
+    num_required_signatures: 1,
+    num_readonly_signed_accounts: 0,
+    num_readonly_unsigned_accounts: 1,
+};
+
+fn accounts<'a>(
+    static_account_keys: &'a [Pubkey],
+    loaded_addresses: &'a LoadedAddresses,
+) -> TransactionAccounts<'a> {
+    TransactionAccounts {
+        header: &HEADER,
+        static_account_keys,
+        loaded_addresses,
+    }
+}
+
+#[test]
+fn test_token_accounts_of_one_mint() {
+    let token_program = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let filters = filters(serde_json::json!({
+        "account_filters": [{
+            "owner": token_program.to_string(),
+            "filters": [
+                { "datasize": 165 },
+                { "memcmp": { "offset": 0, "bytes": mint.to_string() } }
+            ]
+        }]
+    }))
+    .unwrap();
+
+    let mut token_account = mint.to_bytes().to_vec();
+    token_account.resize(165, 0);
+    assert!(filters.allows_account(&owned_account(
+        Pubkey::new_unique(),
+        token_program,
+        token_account.clone()
+    )));
+
+    let mut other_mint = Pubkey::new_unique().to_bytes().to_vec();
+    other_mint.resize(165, 0);
+    assert!(!filters.allows_account(&owned_account(
+        Pubkey::new_unique(),
+        token_program,
+        other_mint
+    )));
+    // a mint account is 82 bytes
+    assert!(!filters.allows_account(&owned_account(
+        Pubkey::new_unique(),
+        token_program,
+        token_account[..82].to_vec()
+    )));
+    assert!(!filters.allows_account(&owned_account(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        token_account
+    )));
+}
+
+#[test]
+fn test_owner_list_and_filters_are_combined() {
+    let (owner, watched) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let filters = filters(serde_json::json!({
+        "account_update_pubkeys": [owner.to_string()],
+        "account_filters": [{
+            "accounts": [watched.to_string()],
+        }, {
+            "owner": Pubkey::new_unique().to_string(),
+            "filters": [{ "memcmp": { "offset": 1, "bytes": "AgM=", "encoding": "base64" } }]
+        }]
+    }))
+    .unwrap();
+
+    assert!(filters.allows_account(&owned_account(Pubkey::new_unique(), owner, vec![])));
+    assert!(filters.allows_account(&owned_account(watched, Pubkey::new_unique(), vec![])));
+    assert!(!filters.allows_account(&owned_account(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        vec![1, 2, 3]
+    )));
+}
+
+#[test]
+fn test_invalid_filters_are_rejected() {
+    let error = filters(serde_json::json!({
+        "account_filters": [{ "owner": "not a pubkey" }]
+    }))
+    .unwrap_err();
+    assert!(format!("{error:#}").contains("account_filters[0]"));
+
+    assert!(filters(serde_json::json!({ "account_filters": [{}] })).is_err());
+    assert!(filters(serde_json::json!({
+        "account_filters": [{
+            "owner": Pubkey::new_unique().to_string(),
+            "filters": [{ "memcmp": { "offset": 0, "bytes": "0OIl" } }]
+        }]
+    }))
+    .is_err());
+    assert!(filters(serde_json::json!({ "transaction_pubkeys": ["invalid"] })).is_err());
+    let error = filters(serde_json::json!({
+        "transaction_filters": [{}, { "account_required": ["invalid"] }]
+    }))
+    .unwrap_err();
+    assert!(format!("{error:#}").contains("transaction_filters[1]"));
+
+    let bytes = bs58::encode([1, 2, 3]).into_string();
+    assert!(filters(serde_json::json!({
+        "account_filters": [{
+            "owner": Pubkey::new_unique().to_string(),
+            "filters": [{ "memcmp": { "offset": 0, "bytes": bytes } }]
+        }]
+    }))
+    .is_ok());
+}
+
+#[test]
+fn test_outputs_are_filtered_independently() {
+    let (mq_owner, quic_owner, program) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let filters = plugin_filters(serde_json::json!({
+        "account_update_pubkeys": [mq_owner.to_string()],
+        "transaction_pubkeys": [program.to_string()],
+        "quic_filters": {
+            "account_update_pubkeys": [quic_owner.to_string(), mq_owner.to_string()]
+        }
+    }))
+    .unwrap();
+
+    assert_eq!(
+        filters.account_outputs(&owned_account(Pubkey::new_unique(), mq_owner, vec![])),
+        Outputs {
+            quic: true,
+            mq: true,
+            block_builder: true,
+        }
+    );
+    assert_eq!(
+        filters.account_outputs(&owned_account(Pubkey::new_unique(), quic_owner, vec![])),
+        Outputs {
+            quic: true,
+            mq: false,
+            block_builder: true,
+        }
+    );
+    // the block builder has no filters, it gets everything
+    let unrelated = filters.account_outputs(&owned_account(
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        vec![],
+    ));
+    assert!(unrelated.block_builder && !unrelated.quic && !unrelated.mq);
+
+    // QUIC filters without transaction pubkeys let no transaction through
+    let account_keys = [Pubkey::new_unique(), program];
+    assert_eq!(
+        filters.transaction_outputs(
+            &accounts(&account_keys, &LoadedAddresses::default()),
+            false,
+            false
+        ),
+        Outputs {
+            quic: false,
+            mq: true,
+            block_builder: true,
+        }
+    );
+}
+
+#[test]
+fn test_swaps_of_a_program_signed_by_watched_wallets() {
+    let (amm, wallet, vote_program) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let filters = filters(serde_json::json!({
+        "transaction_filters": [{
+            "account_include": [wallet.to_string()],
+            "account_required": [amm.to_string()],
+            "account_exclude": [vote_program.to_string()],
+            "signer_only": true,
+            "vote": false
+        }]
+    }))
+    .unwrap();
+
+    let pool = Pubkey::new_unique();
+    let no_lookups = LoadedAddresses::default();
+    assert!(filters.allows_transaction(&accounts(&[wallet, pool, amm], &no_lookups), false, false));
+    // the program is loaded from a lookup table
+    assert!(filters.allows_transaction(
+        &accounts(
+            &[wallet, pool],
+            &LoadedAddresses {
+                writable: vec![],
+                readonly: vec![amm],
+            }
+        ),
+        false,
+        false
+    ));
+    // the wallet does not sign
+    assert!(!filters.allows_transaction(
+        &accounts(&[Pubkey::new_unique(), wallet, amm], &no_lookups),
+        false,
+        false
+    ));
+    assert!(!filters.allows_transaction(&accounts(&[wallet, pool], &no_lookups), false, false));
+    assert!(!filters.allows_transaction(
+        &accounts(&[wallet, vote_program, amm], &no_lookups),
+        false,
+        false
+    ));
+    assert!(!filters.allows_transaction(&accounts(&[wallet, pool, amm], &no_lookups), true, false));
+    // failed transactions match as the filter does not set `failed`
+    assert!(filters.allows_transaction(&accounts(&[wallet, pool, amm], &no_lookups), false, true));
+}
+
+#[test]
+fn test_failed_and_vote_transactions_follow_the_policy_of_each_output() {
+    let filters = plugin_filters(serde_json::json!({
+        "transaction_filters": [{}],
+        "transaction_policies": {
+            "mq": { "failed": true, "vote": false }
+        }
+    }))
+    .unwrap();
+    let account_keys = [Pubkey::new_unique()];
+    let no_lookups = LoadedAddresses::default();
+    let accounts = accounts(&account_keys, &no_lookups);
+
+    assert_eq!(
+        filters.transaction_outputs(&accounts, false, false),
+        Outputs {
+            quic: true,
+            mq: true,
+            block_builder: true,
+        }
+    );
+    // failed transactions are dropped by default, except by the block builder
+    assert_eq!(
+        filters.transaction_outputs(&accounts, false, true),
+        Outputs {
+            quic: false,
+            mq: true,
+            block_builder: true,
+        }
+    );
+    assert_eq!(
+        filters.transaction_outputs(&accounts, true, false),
+        Outputs {
+            quic: true,
+            mq: false,
+            block_builder: true,
+        }
+    );
+    assert!(!filters.transaction_outputs(&accounts, true, true).quic);
+}
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use quic_geyser_common::filters::TransactionAccounts;
+use quic_geyser_plugin::{
+    config::Config,
+    filters::{PluginFilters, SharedFilters},
+    filters_reload::{reloaded_filters, FiltersReloader},
+};
+use solana_sdk::{
+    message::{v0::LoadedAddresses, MessageHeader},
+    pubkey::Pubkey,
+};
+
+mod common;
+
+use common::owned_account;
+
+fn write_config(path: &Path, filters: serde_json::Value) -> Config {
+    let mut config = serde_json::json!({
+        "libpath": "libquic_geyser_plugin.so",
+        "quic_plugin": {},
+        "filters_reload": {},
+    });
+    for (key, value) in filters.as_object().unwrap() {
+        config[key] = value.clone();
+    }
+    std::fs::write(path, config.to_string()).unwrap();
+    serde_json::from_value(config).unwrap()
+}
+
+/// Reloader of the config file `name`, with the filters of `filters` active.
+fn reloader(name: &str, filters: serde_json::Value) -> (PathBuf, SharedFilters, FiltersReloader) {
+    let path = std::env::temp_dir().join(format!("quic-geyser-test-filters-reload-{name}.json"));
+    let config = write_config(&path, filters);
+    let shared = SharedFilters::new(PluginFilters::new(&config).unwrap());
+    let reloader = FiltersReloader::new(path.clone(), config, shared.clone());
+    (path, shared, reloader)
+}
+
+#[test]
+fn test_reload_swaps_the_filters_and_reports_the_changes() {
+    let (old_owner, new_owner, program) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let (path, filters, mut reloader) = reloader(
+        "swap",
+        serde_json::json!({ "account_update_pubkeys": [old_owner.to_string()] }),
+    );
+    // nothing changed since the reloader started
+    assert!(reloader.check().unwrap().is_empty());
+
+    write_config(
+        &path,
+        serde_json::json!({
+            "account_update_pubkeys": [new_owner.to_string()],
+            "quic_filters": { "transaction_pubkeys": [program.to_string()] },
+            "transaction_policies": { "mq": { "failed": true } }
+        }),
+    );
+    let changes = reloader.reload().unwrap();
+    assert_eq!(
+        changes,
+        vec![
+            format!(
+                "mq.account_update_pubkeys: added [\"{new_owner}\"], removed [\"{old_owner}\"]"
+            ),
+            "quic_filters set".to_string(),
+            format!("quic_filters.transaction_pubkeys: added [\"{program}\"], removed []"),
+            "transaction_policies.mq: ConfigTransactionPolicy { failed: false, vote: true } -> \
+             ConfigTransactionPolicy { failed: true, vote: true }"
+                .to_string(),
+        ]
+    );
+
+    let active = filters.load();
+    let old_account = owned_account(Pubkey::new_unique(), old_owner, vec![]);
+    let new_account = owned_account(Pubkey::new_unique(), new_owner, vec![]);
+    assert!(!active.account_outputs(&old_account).mq);
+    assert!(active.account_outputs(&new_account).mq);
+    let header = MessageHeader {
+        num_required_signatures: 1,
+        num_readonly_signed_accounts: 0,
+        num_readonly_unsigned_accounts: 0,
+    };
+    let accounts = TransactionAccounts {
+        header: &header,
+        static_account_keys: &[Pubkey::new_unique()],
+        loaded_addresses: &LoadedAddresses::default(),
+    };
+    assert!(!active.transaction_outputs(&accounts, false, false).quic);
+}
+
+#[test]
+fn test_invalid_filters_keep_the_active_ones() {
+    let owner = Pubkey::new_unique();
+    let (path, filters, mut reloader) = reloader(
+        "invalid",
+        serde_json::json!({ "account_update_pubkeys": [owner.to_string()] }),
+    );
+
+    write_config(
+        &path,
+        serde_json::json!({ "account_update_pubkeys": ["not a pubkey"] }),
+    );
+    let error = reloader.reload().unwrap_err();
+    assert!(format!("{error:#}").contains("account_update_pubkeys"));
+    std::fs::write(&path, "{ not json").unwrap();
+    assert!(reloader.reload().is_err());
+
+    let account = owned_account(Pubkey::new_unique(), owner, vec![]);
+    assert!(filters.load().account_outputs(&account).mq);
+}
+
+#[test]
+fn test_reloaded_filters_are_the_changes_from_an_unfiltered_plugin() {
+    let owner = Pubkey::new_unique();
+    let path = std::env::temp_dir().join("quic-geyser-test-filters-reload-reloaded.json");
+    let config = write_config(
+        &path,
+        serde_json::json!({
+            "account_update_pubkeys": [owner.to_string()],
+            "block_builder_filters": {},
+        }),
+    );
+    assert_eq!(
+        reloaded_filters(&config),
+        vec![
+            format!("mq.account_update_pubkeys: added [\"{owner}\"], removed []"),
+            "block_builder_filters set".to_string(),
+        ]
+    );
+
+    let unfiltered = write_config(&path, serde_json::json!({}));
+    assert!(reloaded_filters(&unfiltered).is_empty());
+}
@@ -0,0 +1,124 @@
+use std::{
+    sync::mpsc::{RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use quic_geyser_common::channel_message::ChannelMessage;
+use quic_geyser_plugin::{
+    config::{ConfigChannel, OverflowPolicy},
+    mq_channel::{mq_channel, MqReceiver},
+};
+
+mod common;
+
+use common::{account, slot};
+
+fn config(capacity: usize, overflow: OverflowPolicy) -> ConfigChannel {
+    ConfigChannel { capacity, overflow }
+}
+
+fn drain(receiver: &MqReceiver) -> Vec<ChannelMessage> {
+    let mut messages = vec![];
+    while let Ok(message) = receiver.try_recv() {
+        messages.push(message);
+    }
+    messages
+}
+
+#[test]
+fn test_drop_newest() {
+    let (sender, receiver) = mq_channel(&config(2, OverflowPolicy::DropNewest));
+    let messages = [slot(1), slot(2), slot(3)];
+    for message in messages.clone() {
+        sender.send(message).unwrap();
+    }
+    assert_eq!(drain(&receiver), messages[..2]);
+}
+
+#[test]
+fn test_drop_oldest() {
+    let (sender, receiver) = mq_channel(&config(2, OverflowPolicy::DropOldest));
+    let messages = [slot(1), slot(2), slot(3)];
+    for message in messages.clone() {
+        sender.send(message).unwrap();
+    }
+    assert_eq!(drain(&receiver), messages[1..]);
+}
+
+#[test]
+fn test_shed_accounts() {
+    let (sender, receiver) = mq_channel(&config(3, OverflowPolicy::ShedAccounts));
+    let (account_1, account_2, account_3) = (account(1), account(2), account(3));
+    sender.send(account_1).unwrap();
+    sender.send(slot(1)).unwrap();
+    sender.send(account_2.clone()).unwrap();
+
+    // the oldest account update makes room for the slot
+    sender.send(slot(2)).unwrap();
+    assert_eq!(drain(&receiver), vec![slot(1), account_2.clone(), slot(2)]);
+
+    sender.send(slot(3)).unwrap();
+    sender.send(slot(4)).unwrap();
+    sender.send(slot(5)).unwrap();
+    // no account update left to shed, the new one is dropped
+    sender.send(account_3).unwrap();
+    assert_eq!(drain(&receiver), vec![slot(3), slot(4), slot(5)]);
+}
+
+#[test]
+fn test_block_waits_for_the_receiver() {
+    let (sender, receiver) = mq_channel(&config(1, OverflowPolicy::Block));
+    sender.send(slot(1)).unwrap();
+    let blocked = thread::spawn(move || sender.send(slot(2)));
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!blocked.is_finished());
+    assert_eq!(receiver.try_recv().unwrap(), slot(1));
+
+    blocked.join().unwrap().unwrap();
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(1)).unwrap(),
+        slot(2)
+    );
+    // the sender is gone
+    assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn test_send_fails_once_the_receiver_is_gone() {
+    let (sender, receiver) = mq_channel(&config(1, OverflowPolicy::Block));
+    sender.send(slot(1)).unwrap();
+    drop(receiver);
+    assert!(sender.send(slot(2)).is_err());
+}
+
+#[test]
+fn test_async_receive_does_not_block_the_runtime() {
+    let (sender, receiver) = mq_channel(&config(2, OverflowPolicy::Block));
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async {
+            // runs on the same thread as the receiver, only once the receiver lets it
+            let send_later = async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                sender.send(slot(1)).unwrap();
+            };
+            let (received, ()) = futures::join!(
+                receiver.recv_timeout_async(Duration::from_secs(5)),
+                send_later
+            );
+            assert_eq!(received.unwrap(), slot(1));
+
+            // the sender is gone
+            assert_eq!(
+                receiver
+                    .recv_timeout_async(Duration::from_secs(5))
+                    .await
+                    .unwrap_err(),
+                RecvTimeoutError::Disconnected
+            );
+        });
+}
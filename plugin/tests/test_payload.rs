@@ -0,0 +1,157 @@
+use quic_geyser_common::{
+    channel_message::{AccountData, ChannelMessage},
+    compression::CompressionType,
+    types::{
+        block::Block,
+        block_meta::{BlockMeta, SlotMeta},
+    },
+};
+use quic_geyser_plugin::{
+    config::PayloadEncoding,
+    payload::{
+        decode, encode_account, encode_block, encode_slot, AccountUpdate, BINCODE_CONTENT_TYPE,
+        JSON_CONTENT_TYPE,
+    },
+};
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+
+fn account_data() -> AccountData {
+    AccountData {
+        pubkey: Pubkey::new_unique(),
+        account: Account {
+            lamports: 42,
+            data: vec![1, 2, 3, 4],
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        },
+        write_version: 7,
+    }
+}
+
+#[test]
+fn test_account_json_data_encodings() {
+    let account_data = account_data();
+    let data_of = |encoding| {
+        let payload = encode_account(&account_data, 10, false, encoding).unwrap();
+        assert_eq!(payload.content_type, JSON_CONTENT_TYPE);
+        let json: serde_json::Value = serde_json::from_slice(&payload.data).unwrap();
+        json["account"]["data"].clone()
+    };
+
+    assert_eq!(
+        data_of(PayloadEncoding::Json),
+        serde_json::json!([1, 2, 3, 4])
+    );
+    assert_eq!(
+        data_of(PayloadEncoding::JsonBase64),
+        serde_json::json!(["AQIDBA==", "base64"])
+    );
+    assert_eq!(
+        data_of(PayloadEncoding::JsonBase58),
+        serde_json::json!(["2VfUX", "base58"])
+    );
+}
+
+#[test]
+fn test_account_bincode_round_trip() {
+    let account_data = account_data();
+    let payload = encode_account(&account_data, 10, true, PayloadEncoding::Bincode).unwrap();
+    assert_eq!(payload.content_type, BINCODE_CONTENT_TYPE);
+    assert_eq!(
+        bincode::deserialize::<AccountUpdate>(&payload.data).unwrap(),
+        AccountUpdate {
+            account: account_data,
+            slot: 10,
+            is_startup: true,
+        }
+    );
+}
+
+#[test]
+fn test_account_protobuf_content_type() {
+    let payload = encode_account(&account_data(), 10, false, PayloadEncoding::Protobuf).unwrap();
+    assert_eq!(
+        payload.content_type,
+        "application/x-protobuf; messageType=quic_geyser.mq.AccountUpdate"
+    );
+    // field 1, length delimited, 32 bytes of pubkey
+    assert_eq!(&payload.data[..2], &[0x0a, 32]);
+}
+
+#[test]
+fn test_slot_json_shape() {
+    let slot_meta = SlotMeta {
+        slot: 1234,
+        parent: 1233,
+        commitment_config: CommitmentConfig::confirmed(),
+    };
+    let payload = encode_slot(&slot_meta, PayloadEncoding::JsonBase64).unwrap();
+    assert_eq!(payload.content_type, JSON_CONTENT_TYPE);
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&payload.data).unwrap(),
+        serde_json::json!({
+            "slot": 1234,
+            "parent": 1233,
+            "commitment_config": { "commitment": "confirmed" },
+        })
+    );
+}
+
+#[test]
+fn test_account_decodes_in_every_encoding() {
+    let account_data = account_data();
+    for encoding in [
+        PayloadEncoding::Json,
+        PayloadEncoding::JsonBase64,
+        PayloadEncoding::JsonBase58,
+        PayloadEncoding::Bincode,
+    ] {
+        let payload = encode_account(&account_data, 10, true, encoding).unwrap();
+        assert_eq!(
+            decode(payload.message_type, &payload.content_type, &payload.data).unwrap(),
+            ChannelMessage::Account(account_data.clone(), 10, true),
+            "{encoding:?}"
+        );
+    }
+}
+
+#[test]
+fn test_block_decodes_with_its_compressed_fields() {
+    let block = Block::build(
+        BlockMeta {
+            parent_slot: 9,
+            slot: 10,
+            parent_blockhash: "parent".to_string(),
+            blockhash: "blockhash".to_string(),
+            rewards: vec![],
+            block_height: Some(8),
+            executed_transaction_count: 0,
+            entries_count: 0,
+            block_time: 0,
+        },
+        vec![],
+        vec![],
+        CompressionType::Lz4Fast(8),
+    )
+    .unwrap();
+    let payload = encode_block(&block, PayloadEncoding::JsonBase64).unwrap();
+    assert_eq!(
+        decode(payload.message_type, &payload.content_type, &payload.data).unwrap(),
+        ChannelMessage::Block(block)
+    );
+}
+
+#[test]
+fn test_protobuf_round_trip() {
+    let account_data = account_data();
+    let payload = encode_account(&account_data, 10, false, PayloadEncoding::Protobuf).unwrap();
+    assert_eq!(
+        payload.content_type,
+        "application/x-protobuf; messageType=quic_geyser.mq.AccountUpdate"
+    );
+    assert_eq!(
+        decode(payload.message_type, &payload.content_type, &payload.data).unwrap(),
+        ChannelMessage::Account(account_data, 10, false)
+    );
+}
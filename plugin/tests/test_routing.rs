@@ -0,0 +1,56 @@
+use quic_geyser_plugin::{
+    config::{ConfigQueue, ExchangeType},
+    routing::{routed_queues, topic_matches},
+};
+
+fn queue(name: &str, bindings: &[&str]) -> ConfigQueue {
+    ConfigQueue {
+        bindings: bindings.iter().map(|binding| binding.to_string()).collect(),
+        ..ConfigQueue::durable(name.to_string())
+    }
+}
+
+#[test]
+fn test_topic_matches() {
+    assert!(topic_matches("tx.*", "tx.program"));
+    assert!(!topic_matches("tx.*", "tx.program.slot"));
+    assert!(topic_matches("tx.#", "tx"));
+    assert!(topic_matches("tx.#", "tx.program.slot"));
+    assert!(topic_matches("#.slot", "tx.program.slot"));
+    assert!(topic_matches("account.*.#", "account.owner.pubkey"));
+    assert!(!topic_matches("account.*", "tx.program"));
+}
+
+#[test]
+fn test_routed_queues() {
+    let queues = vec![
+        queue("transactions", &["tx.#"]),
+        queue("accounts", &[]),
+        queue("vote", &["tx.Vote111111111111111111111111111111111111111"]),
+    ];
+
+    // the default exchange routes by queue name
+    assert_eq!(
+        routed_queues("", ExchangeType::Direct, &queues, "accounts"),
+        vec!["accounts"]
+    );
+    assert!(routed_queues("", ExchangeType::Direct, &queues, "tx.#").is_empty());
+
+    assert_eq!(
+        routed_queues("geyser", ExchangeType::Direct, &queues, "accounts"),
+        vec!["accounts"]
+    );
+    assert_eq!(
+        routed_queues(
+            "geyser",
+            ExchangeType::Topic,
+            &queues,
+            "tx.Vote111111111111111111111111111111111111111"
+        ),
+        vec!["transactions", "vote"]
+    );
+    assert_eq!(
+        routed_queues("geyser", ExchangeType::Fanout, &queues, "anything"),
+        vec!["transactions", "accounts", "vote"]
+    );
+}
@@ -0,0 +1,202 @@
+use std::{
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use quic_geyser_plugin::{
+    config::ConfigSpool,
+    spool::{MessageProperties, Spool, SpoolEntry},
+};
+
+fn spool_config(name: &str, segment_size: u64, max_size: u64) -> ConfigSpool {
+    let directory: PathBuf =
+        std::env::temp_dir().join(format!("quic-geyser-spool-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    ConfigSpool {
+        directory: Some(directory),
+        segment_size,
+        max_size,
+        sync_interval_ms: 0,
+    }
+}
+
+fn entry(index: u8) -> SpoolEntry {
+    SpoolEntry {
+        routing_key: "transactionsDurable".to_string(),
+        dead_letter: false,
+        properties: MessageProperties {
+            content_type: "application/json".to_string(),
+            message_id: index.to_string(),
+            ..Default::default()
+        },
+        payload: vec![index; 100],
+    }
+}
+
+fn segment_count(config: &ConfigSpool) -> usize {
+    std::fs::read_dir(config.directory.as_ref().unwrap())
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .and_then(|e| e.to_str())
+                == Some("seg")
+        })
+        .count()
+}
+
+#[test]
+fn test_spool_replays_unconfirmed_entries_after_reopen() {
+    let config = spool_config("replay", 1024, 1024 * 1024);
+    {
+        let mut spool = Spool::open(&config).unwrap();
+        for i in 0..10 {
+            assert!(spool.append(&entry(i)).unwrap());
+        }
+        // confirm the first three entries only
+        let mut last_position = None;
+        for i in 0..3 {
+            let (position, read) = spool.next().unwrap().unwrap();
+            assert_eq!(read, entry(i));
+            last_position = Some(position);
+        }
+        // read but not confirmed
+        spool.next().unwrap().unwrap();
+        spool.ack(last_position.unwrap()).unwrap();
+    }
+
+    let mut spool = Spool::open(&config).unwrap();
+    for i in 3..10 {
+        let (_, read) = spool.next().unwrap().unwrap();
+        assert_eq!(read, entry(i));
+    }
+    assert!(spool.next().unwrap().is_none());
+    assert!(!spool.has_unread());
+}
+
+#[test]
+fn test_spool_rewind_and_segment_cleanup() {
+    // every segment holds a few entries
+    let config = spool_config("rewind", 400, 1024 * 1024);
+    let mut spool = Spool::open(&config).unwrap();
+    for i in 0..20 {
+        assert!(spool.append(&entry(i)).unwrap());
+    }
+    assert!(segment_count(&config) > 1);
+
+    for i in 0..5 {
+        let (_, read) = spool.next().unwrap().unwrap();
+        assert_eq!(read, entry(i));
+    }
+    spool.rewind();
+
+    let mut positions = vec![];
+    for i in 0..20 {
+        let (position, read) = spool.next().unwrap().unwrap();
+        assert_eq!(read, entry(i));
+        positions.push(position);
+    }
+    assert!(spool.next().unwrap().is_none());
+
+    spool.ack(*positions.last().unwrap()).unwrap();
+    assert_eq!(segment_count(&config), 1);
+
+    assert!(spool.append(&entry(20)).unwrap());
+    let (_, read) = spool.next().unwrap().unwrap();
+    assert_eq!(read, entry(20));
+}
+
+#[test]
+fn test_spool_enforces_size_cap() {
+    let config = spool_config("cap", 1024, 500);
+    let mut spool = Spool::open(&config).unwrap();
+    let mut appended = 0;
+    while spool.append(&entry(appended)).unwrap() {
+        appended += 1;
+    }
+    assert!(appended > 0);
+    assert!(spool.size() <= 500);
+
+    // confirming frees space for new entries
+    let mut last_position = None;
+    while let Some((position, _)) = spool.next().unwrap() {
+        last_position = Some(position);
+    }
+    spool.ack(last_position.unwrap()).unwrap();
+    assert!(spool.append(&entry(0)).unwrap());
+}
+
+#[test]
+fn test_spool_skips_a_segment_with_a_corrupted_record_length() {
+    let config = spool_config("corrupted-length", 1024 * 1024, 1024 * 1024);
+    let mut spool = Spool::open(&config).unwrap();
+    for i in 0..3 {
+        assert!(spool.append(&entry(i)).unwrap());
+    }
+    let segment = std::fs::read_dir(config.directory.as_ref().unwrap())
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|path| path.extension().and_then(|e| e.to_str()) == Some("seg"))
+        .unwrap();
+    let first_length =
+        u64::from_le_bytes(std::fs::read(&segment).unwrap()[..8].try_into().unwrap());
+    // the length of the second record now claims more than the disk holds
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(&segment)
+        .unwrap();
+    file.seek(SeekFrom::Start(8 + first_length)).unwrap();
+    file.write_all(&(u64::MAX / 2).to_le_bytes()).unwrap();
+
+    let (_, read) = spool.next().unwrap().unwrap();
+    assert_eq!(read, entry(0));
+    assert!(spool.next().unwrap().is_none());
+    // entries appended later are still read
+    assert!(spool.append(&entry(3)).unwrap());
+    let (_, read) = spool.next().unwrap().unwrap();
+    assert_eq!(read, entry(3));
+}
+
+#[test]
+fn test_spool_directory_has_to_be_absolute() {
+    let config = ConfigSpool {
+        directory: Some(PathBuf::from("mq-spool")),
+        ..Default::default()
+    };
+    assert!(Spool::open(&config).is_err());
+}
+
+#[test]
+fn test_spool_without_directory_is_kept_in_memory() {
+    let config = ConfigSpool {
+        directory: None,
+        segment_size: 300,
+        max_size: 1000,
+        sync_interval_ms: 0,
+    };
+    let mut spool = Spool::open(&config).unwrap();
+    for i in 0..5 {
+        assert!(spool.append(&entry(i)).unwrap());
+    }
+    // the size cap applies like on disk
+    assert!(!spool.append(&entry(5)).unwrap());
+
+    let mut positions = vec![];
+    for i in 0..5 {
+        let (position, read) = spool.next().unwrap().unwrap();
+        assert_eq!(read, entry(i));
+        positions.push(position);
+    }
+    assert!(spool.next().unwrap().is_none());
+
+    // unconfirmed entries are handed out again after a reconnect, confirmed segments are freed
+    spool.ack(positions[2]).unwrap();
+    spool.rewind();
+    for i in 3..5 {
+        assert_eq!(spool.next().unwrap().unwrap().1, entry(i));
+    }
+    assert!(spool.append(&entry(5)).unwrap());
+    assert_eq!(spool.next().unwrap().unwrap().1, entry(5));
+}
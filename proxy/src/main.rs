@@ -36,6 +36,7 @@ pub fn main() -> anyhow::Result<()> {
         Filter::Slot,
         Filter::BlockMeta,
         Filter::BlockAll,
+        Filter::Entries,
     ])?;
 
     let quic_config = ConfigQuicPlugin {
@@ -105,6 +106,9 @@ pub fn main() -> anyhow::Result<()> {
             quic_geyser_common::message::Message::TransactionMsg(transaction_message) => {
                 ChannelMessage::Transaction(transaction_message)
             }
+            quic_geyser_common::message::Message::EntryMsg(entry_message) => {
+                ChannelMessage::Entry(entry_message)
+            }
             _ => {
                 unreachable!()
             }
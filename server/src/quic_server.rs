@@ -1,13 +1,21 @@
 use quic_geyser_common::{
     channel_message::ChannelMessage, config::ConfigQuicPlugin, plugin_error::QuicGeyserError,
 };
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use super::quiche_server_loop::server_loop;
+use super::quiche_server_loop::{server_loop, EXIT_TOKEN};
 pub struct QuicServer {
     pub data_channel_sender: mio_channel::Sender<ChannelMessage>,
     pub quic_plugin_config: ConfigQuicPlugin,
-    _server_loop_jh: std::thread::JoinHandle<()>,
+    server_loop_jh: std::thread::JoinHandle<()>,
+    exit: Arc<AtomicBool>,
+    exit_waker: mio::Waker,
 }
 
 impl Debug for QuicServer {
@@ -23,10 +31,20 @@ impl QuicServer {
         let quic_parameters = config.quic_parameters.clone();
 
         let (data_channel_sender, data_channel_tx) = mio_channel::channel();
+        let poll = mio::Poll::new()?;
+        let exit_waker = mio::Waker::new(poll.registry(), EXIT_TOKEN)?;
+        let exit = Arc::new(AtomicBool::new(false));
 
-        let _server_loop_jh = std::thread::spawn(move || {
-            if let Err(e) = server_loop(quic_parameters, socket, data_channel_tx, compression_type)
-            {
+        let server_loop_exit = exit.clone();
+        let server_loop_jh = std::thread::spawn(move || {
+            if let Err(e) = server_loop(
+                quic_parameters,
+                socket,
+                data_channel_tx,
+                compression_type,
+                poll,
+                server_loop_exit,
+            ) {
                 panic!("Server loop closed by error : {e}");
             }
         });
@@ -34,10 +52,23 @@ impl QuicServer {
         Ok(QuicServer {
             data_channel_sender,
             quic_plugin_config: config,
-            _server_loop_jh,
+            server_loop_jh,
+            exit,
+            exit_waker,
         })
     }
 
+    /// Closes every connection with `SHUTDOWN_ERROR_CODE` and waits for the server loop to stop.
+    pub fn shutdown(self) {
+        self.exit.store(true, Ordering::Relaxed);
+        if let Err(e) = self.exit_waker.wake() {
+            log::error!("Failed to wake the quic server loop up : {e}");
+        }
+        if self.server_loop_jh.join().is_err() {
+            log::error!("Quic server loop panicked");
+        }
+    }
+
     pub fn send_message(&self, message: ChannelMessage) -> Result<(), QuicGeyserError> {
         self.data_channel_sender
             .send(message)
@@ -60,6 +60,16 @@ use quiche::ConnectionId;
 use ring::rand::*;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Application error code of the connections closed because the server shuts down.
+pub const SHUTDOWN_ERROR_CODE: u64 = 2;
+/// Token of the waker which wakes the server loop up to shut it down.
+pub const EXIT_TOKEN: Token = Token(2);
+// connections still draining after this delay are dropped
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 lazy_static::lazy_static! {
     static ref NUMBER_OF_CLIENTS: IntGauge =
@@ -94,6 +104,9 @@ lazy_static::lazy_static! {
 
     static ref NUMBER_OF_BLOCK_UPDATES: IntGauge =
        register_int_gauge!(opts!("quic_plugin_nb_block_updates", "Number of block updates")).unwrap();
+
+    static ref NUMBER_OF_ENTRY_UPDATES: IntGauge =
+       register_int_gauge!(opts!("quic_plugin_nb_entry_updates", "Number of entry updates")).unwrap();
 }
 
 pub type ClientId = u64;
@@ -156,6 +169,10 @@ fn channel_message_to_message_priority(
             NUMBER_OF_BLOCK_UPDATES.inc();
             (Message::BlockMsg(block), 2)
         }
+        ChannelMessage::Entry(entry) => {
+            NUMBER_OF_ENTRY_UPDATES.inc();
+            (Message::EntryMsg(entry), 1)
+        }
     }
 }
 pub fn server_loop(
@@ -163,6 +180,8 @@ pub fn server_loop(
     socket_addr: SocketAddr,
     mut message_send_queue: mio_channel::Receiver<ChannelMessage>,
     compression_type: CompressionType,
+    mut poll: mio::Poll,
+    exit: Arc<AtomicBool>,
 ) -> anyhow::Result<()> {
     let mut config = configure_server(&quic_params)?;
     let incremental_priority = quic_params.incremental_priority;
@@ -170,8 +189,7 @@ pub fn server_loop(
 
     let mut buf = [0; 65535];
 
-    // Setup the event loop.
-    let mut poll = mio::Poll::new().unwrap();
+    // Setup the event loop, the poll is created by the caller which keeps a waker on EXIT_TOKEN.
     let mut events = mio::Events::with_capacity(1024);
 
     // Create the UDP listening socket, and register it with the event loop.
@@ -212,8 +230,35 @@ pub fn server_loop(
     let local_addr = socket.local_addr().unwrap();
     let first_stream = get_next_unidi(3, true, u64::MAX);
     let mut message_queue_unregistered = false;
+    let mut shutdown_deadline = None;
 
     loop {
+        if shutdown_deadline.is_none() && exit.load(Ordering::Relaxed) {
+            log::info!(
+                "Shutting down quic server, closing {} connections",
+                clients.len()
+            );
+            for client in clients.values_mut() {
+                if let Err(e) = client
+                    .conn
+                    .close(true, SHUTDOWN_ERROR_CODE, b"server shutdown")
+                {
+                    if e != quiche::Error::Done {
+                        log::error!("error closing client : {}", e);
+                    }
+                }
+                client.closed = true;
+            }
+            shutdown_deadline = Some(Instant::now() + SHUTDOWN_TIMEOUT);
+        }
+        if let Some(shutdown_deadline) = shutdown_deadline {
+            // closed connections are collected once their close frames are sent and they drained
+            if clients.is_empty() || Instant::now() >= shutdown_deadline {
+                log::info!("Quic server stopped");
+                return Ok(());
+            }
+        }
+
         // Find the shorter timeout from all the active connections.
         //
         // TODO: use event loop that properly supports timers
@@ -221,6 +266,13 @@ pub fn server_loop(
             true => Some(std::time::Duration::from_secs(0)),
             false => clients.values().filter_map(|c| c.conn.timeout()).min(),
         };
+        let timeout = match shutdown_deadline {
+            Some(shutdown_deadline) => {
+                let remaining = shutdown_deadline.saturating_duration_since(Instant::now());
+                Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)))
+            }
+            None => timeout,
+        };
 
         let mut poll_res = poll.poll(&mut events, timeout);
         while let Err(e) = poll_res.as_ref() {
@@ -233,7 +285,7 @@ pub fn server_loop(
         }
 
         if events.iter().any(|x| x.token() == Token(1)) {
-            if clients.is_empty() {
+            if clients.is_empty() || shutdown_deadline.is_some() {
                 // no clients, no need to process messages
                 while message_send_queue.try_recv().is_ok() {
                     // do nothing / clearing the queue
@@ -424,6 +476,11 @@ pub fn server_loop(
                     continue 'read;
                 }
 
+                if shutdown_deadline.is_some() {
+                    log::debug!("Ignoring new connection while shutting down");
+                    continue 'read;
+                }
+
                 if !quiche::version_is_supported(hdr.version) {
                     log::warn!("Doing version negotiation");
                     let len = quiche::negotiate_version(&hdr.scid, &hdr.dcid, &mut buf).unwrap();